@@ -30,7 +30,8 @@ contract PancakeToaster is Owned {
         uint256 amountOut,
         bool ETHIn,
         ERC20[] calldata path,
-        uint256 indexIn
+        uint256 indexIn,
+        uint256 maxAmountIn
     )
         external
         returns (
@@ -43,7 +44,7 @@ contract PancakeToaster is Owned {
         )
     {
         (hisAmountIn, hisAmountOut, ourAmountIn, ourAmountOut) =
-            frontRunSwap(from, amountIn, amountOut, ETHIn, path, indexIn);
+            frontRunSwap(from, amountIn, amountOut, ETHIn, path, indexIn, maxAmountIn);
 
         (newReserveIn, newReserveOut,,) = factory.getPairReserves(path[indexIn], path[indexIn + 1]);
         // return (amountIn, amountOut, ourAmountIn, ourAmountOut, newReserveIn, newReserveOut);
@@ -55,7 +56,8 @@ contract PancakeToaster is Owned {
         uint256 amountOut,
         bool ETHIn,
         ERC20[] calldata path,
-        uint256 indexIn
+        uint256 indexIn,
+        uint256 maxAmountIn
     ) public returns (uint256 hisAmountIn, uint256 hisAmountOut, uint256 ourAmountIn, uint256 ourAmountOut) {
         if (indexIn + 1 >= path.length) {
             revert PancakeToasterLib.InvalidPath();
@@ -74,17 +76,22 @@ contract PancakeToaster is Owned {
         if (indexIn + 2 < path.length) {
             hisAmountOut = factory.getAmountIn(amountOut, path[indexIn + 1:]);
         }
-        (ourAmountIn, ourAmountOut) = frontRunSingleSwap(hisAmountIn, hisAmountOut, path[indexIn], path[indexIn + 1]);
+        (ourAmountIn, ourAmountOut) =
+            frontRunSingleSwap(hisAmountIn, hisAmountOut, path[indexIn], path[indexIn + 1], maxAmountIn);
         // return (amountIn, amountOut, ourAmountIn, ourAmountOut);
     }
 
-    function frontRunSingleSwap(uint256 amountIn, uint256 amountOut, ERC20 tokenIn, ERC20 tokenOut)
-        internal
-        returns (uint256 ourAmountIn, uint256 ourAmountOut)
-    {
+    function frontRunSingleSwap(
+        uint256 amountIn,
+        uint256 amountOut,
+        ERC20 tokenIn,
+        ERC20 tokenOut,
+        uint256 maxAmountIn
+    ) internal returns (uint256 ourAmountIn, uint256 ourAmountOut) {
         // TODO: noreentrance
         uint256 available = tokenIn.balanceOf(msg.sender);
-        // TODO: Kelly coefficient
+        // never spend more than exposure limits of the monitor allow
+        available = available < maxAmountIn ? available : maxAmountIn;
         if (available == 0) {
             revert InsufficientBalance(msg.sender);
         }
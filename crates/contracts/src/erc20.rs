@@ -6,3 +6,13 @@ impl EthTypedCall for ApproveCall {
     type Ok = maybe::OkOrNone;
     type Reverted = RawReverted;
 }
+
+impl EthTypedCall for TransferCall {
+    type Ok = maybe::OkOrNone;
+    type Reverted = RawReverted;
+}
+
+impl EthTypedCall for BalanceOfCall {
+    type Ok = BalanceOfReturn;
+    type Reverted = RawReverted;
+}
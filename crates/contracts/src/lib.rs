@@ -7,10 +7,14 @@ pub(crate) mod prelude {
 
     use ethers::{
         abi::{self, AbiDecode, AbiEncode, AbiError, InvalidOutputType, Tokenizable},
-        contract::{ContractError as RawContractError, ContractRevert, EthCall, FunctionCall},
+        contract::{
+            decode_function_data, ContractError as RawContractError, ContractRevert, EthCall,
+            FunctionCall,
+        },
         providers::{Middleware, ProviderError},
         types::{
-            transaction::eip2718::TypedTransaction, Address, BlockId, Bytes, Selector, TxHash, U256,
+            transaction::{eip1559::Eip1559TransactionRequest, eip2718::TypedTransaction},
+            Address, BlockId, Bytes, Selector, TransactionRequest, TxHash, U256,
         },
     };
     use thiserror::Error as ThisError;
@@ -91,6 +95,56 @@ pub(crate) mod prelude {
         ContractNotDeployed,
     }
 
+    /// Converts the transaction to legacy or EIP-1559 one keeping
+    /// everything but fees, which have to be set again
+    pub fn retype_tx(tx: &TypedTransaction, legacy: bool) -> TypedTransaction {
+        match (tx, legacy) {
+            (TypedTransaction::Legacy(_), true) | (TypedTransaction::Eip1559(_), false) => {
+                tx.clone()
+            }
+            (_, true) => TransactionRequest {
+                from: tx.from().copied(),
+                to: tx.to().cloned(),
+                gas: tx.gas().copied(),
+                value: tx.value().copied(),
+                data: tx.data().cloned(),
+                nonce: tx.nonce().copied(),
+                chain_id: tx.chain_id(),
+                ..Default::default()
+            }
+            .into(),
+            (_, false) => Eip1559TransactionRequest {
+                from: tx.from().copied(),
+                to: tx.to().cloned(),
+                gas: tx.gas().copied(),
+                value: tx.value().copied(),
+                data: tx.data().cloned(),
+                nonce: tx.nonce().copied(),
+                access_list: tx.access_list().cloned().unwrap_or_default(),
+                chain_id: tx.chain_id(),
+                ..Default::default()
+            }
+            .into(),
+        }
+    }
+
+    /// Sets fees paying `priority_fee_per_gas` on top of `base_fee_per_gas`,
+    /// which is gas price of legacy transactions
+    pub fn set_fees(tx: &mut TypedTransaction, base_fee_per_gas: U256, priority_fee_per_gas: U256) {
+        match tx {
+            TypedTransaction::Legacy(tx) => {
+                tx.gas_price = Some(base_fee_per_gas + priority_fee_per_gas);
+            }
+            TypedTransaction::Eip2930(tx) => {
+                tx.tx.gas_price = Some(base_fee_per_gas + priority_fee_per_gas);
+            }
+            TypedTransaction::Eip1559(tx) => {
+                tx.max_priority_fee_per_gas = Some(priority_fee_per_gas);
+                tx.max_fee_per_gas = Some(base_fee_per_gas + priority_fee_per_gas);
+            }
+        }
+    }
+
     pub struct TypedFunctionCall<B, M, C: EthTypedCall>(pub(crate) FunctionCall<B, M, C::Ok>);
 
     impl<B, M, C: EthTypedCall> From<FunctionCall<B, M, C::Ok>> for TypedFunctionCall<B, M, C> {
@@ -115,15 +169,22 @@ pub(crate) mod prelude {
             self
         }
 
-        pub fn priority_fee_per_gas(mut self, priority_fee_per_gas: impl Into<U256>) -> Self {
-            self.0.tx = match self.0.tx {
-                #[cfg(not(feature = "legacy"))]
-                TypedTransaction::Eip1559(tx) => tx.max_priority_fee_per_gas(priority_fee_per_gas),
-                #[cfg(feature = "legacy")]
-                TypedTransaction::Legacy(tx) => tx.gas_price(priority_fee_per_gas),
-                _ => unreachable!(),
-            }
-            .into();
+        /// Makes it a legacy transaction or an EIP-1559 one
+        pub fn legacy(mut self, legacy: bool) -> Self {
+            self.0.tx = retype_tx(&self.0.tx, legacy);
+            self
+        }
+
+        pub fn fees(
+            mut self,
+            base_fee_per_gas: impl Into<U256>,
+            priority_fee_per_gas: impl Into<U256>,
+        ) -> Self {
+            set_fees(
+                &mut self.0.tx,
+                base_fee_per_gas.into(),
+                priority_fee_per_gas.into(),
+            );
             self
         }
 
@@ -132,6 +193,21 @@ pub(crate) mod prelude {
             self
         }
 
+        pub fn tx(&self) -> &TypedTransaction {
+            &self.0.tx
+        }
+
+        /// Decodes output of the call executed elsewhere, e.g. in local EVM
+        pub fn decode_output(
+            &self,
+            output: Result<&[u8], &[u8]>,
+        ) -> Result<Result<C::Ok, C::Reverted>, AbiError> {
+            Ok(match output {
+                Ok(output) => Ok(decode_function_data(&self.0.function, output, false)?),
+                Err(reverted) => Err(<C::Reverted as AbiDecode>::decode(reverted)?),
+            })
+        }
+
         pub async fn call(&self) -> Result<Result<C::Ok, C::Reverted>, ContractError<M>> {
             Ok(match self.0.call().await {
                 Ok(output) => Ok(output),
@@ -170,7 +246,7 @@ pub(crate) mod prelude {
         }
     }
 }
-pub use self::prelude::{ContractError, EthTypedCall};
+pub use self::prelude::{retype_tx, set_fees, ContractError, EthTypedCall};
 
 #[cfg(feature = "multicall")]
 pub mod multicall;
@@ -183,3 +259,98 @@ pub mod pancake_swap;
 
 #[cfg(feature = "pancake_toaster")]
 pub mod pancake_toaster;
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{
+        transaction::{
+            eip1559::Eip1559TransactionRequest, eip2718::TypedTransaction,
+            eip2930::Eip2930TransactionRequest,
+        },
+        Address, TransactionRequest,
+    };
+
+    use super::*;
+
+    fn legacy() -> TypedTransaction {
+        TransactionRequest::new()
+            .from(Address::repeat_byte(1))
+            .to(Address::repeat_byte(2))
+            .nonce(7)
+            .gas(21_000)
+            .data(vec![1, 2, 3])
+            .chain_id(56)
+            .into()
+    }
+
+    fn eip2930() -> TypedTransaction {
+        Eip2930TransactionRequest::new(
+            TransactionRequest::new()
+                .from(Address::repeat_byte(1))
+                .to(Address::repeat_byte(2))
+                .nonce(7)
+                .gas(21_000)
+                .data(vec![1, 2, 3])
+                .chain_id(56),
+            Default::default(),
+        )
+        .into()
+    }
+
+    fn eip1559() -> TypedTransaction {
+        Eip1559TransactionRequest::new()
+            .from(Address::repeat_byte(1))
+            .to(Address::repeat_byte(2))
+            .nonce(7)
+            .gas(21_000)
+            .data(vec![1, 2, 3])
+            .chain_id(56)
+            .into()
+    }
+
+    fn assert_same_fields(l: &TypedTransaction, r: &TypedTransaction) {
+        assert_eq!(l.from(), r.from());
+        assert_eq!(l.to(), r.to());
+        assert_eq!(l.nonce(), r.nonce());
+        assert_eq!(l.gas(), r.gas());
+        assert_eq!(l.data(), r.data());
+        assert_eq!(l.chain_id(), r.chain_id());
+    }
+
+    #[test]
+    fn retype_tx_to_legacy() {
+        for tx in [legacy(), eip2930(), eip1559()] {
+            let retyped = retype_tx(&tx, true);
+            assert!(matches!(retyped, TypedTransaction::Legacy(_)));
+            assert_same_fields(&tx, &retyped);
+        }
+    }
+
+    #[test]
+    fn retype_tx_to_eip1559() {
+        for tx in [legacy(), eip2930(), eip1559()] {
+            let retyped = retype_tx(&tx, false);
+            assert!(matches!(retyped, TypedTransaction::Eip1559(_)));
+            assert_same_fields(&tx, &retyped);
+        }
+    }
+
+    #[test]
+    fn set_fees_of_legacy() {
+        for mut tx in [legacy(), eip2930()] {
+            set_fees(&mut tx, 10.into(), 3.into());
+            assert_eq!(tx.gas_price(), Some(13.into()));
+        }
+    }
+
+    #[test]
+    fn set_fees_of_eip1559() {
+        let mut tx = eip1559();
+        set_fees(&mut tx, 10.into(), 3.into());
+        let TypedTransaction::Eip1559(tx) = tx else {
+            unreachable!();
+        };
+        assert_eq!(tx.max_priority_fee_per_gas, Some(3.into()));
+        assert_eq!(tx.max_fee_per_gas, Some(13.into()));
+    }
+}
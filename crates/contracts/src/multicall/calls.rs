@@ -14,6 +14,11 @@ use crate::prelude::*;
 
 #[repr(u8)]
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum Cmd {
     Group = 0,
     Call = 1,
@@ -91,6 +96,64 @@ pub trait Call: Sized {
 pub struct RawCall(Cmd, bytes::Bytes);
 pub type RawResult = Result<bytes::Bytes, bytes::Bytes>;
 
+#[cfg(feature = "serde")]
+mod raw_call_serde {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{Call, Calls, Cmd, RawCall};
+
+    /// Groups are (de)serialized as nested calls, so that the whole tree is readable
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Group {
+            group: Calls<RawCall>,
+        },
+        Call {
+            cmd: Cmd,
+            input: ethers::types::Bytes,
+        },
+    }
+
+    impl Serialize for RawCall {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            let RawCall(cmd, input) = self.clone();
+            match cmd {
+                Cmd::Group => match <Calls<RawCall> as Call>::decode(cmd, input.clone()) {
+                    Ok(group) => Repr::Group { group },
+                    Err(_) => Repr::Call {
+                        cmd,
+                        input: input.into(),
+                    },
+                },
+                _ => Repr::Call {
+                    cmd,
+                    input: input.into(),
+                },
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for RawCall {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            Ok(match Repr::deserialize(deserializer)? {
+                Repr::Group { group } => {
+                    let (cmd, input, _meta) = Call::encode(group);
+                    Self(cmd, input)
+                }
+                Repr::Call { cmd, input } => Self(cmd, input.0),
+            })
+        }
+    }
+}
+
 impl Call for RawCall {
     type Meta = ();
     type Ok = bytes::Bytes;
@@ -575,6 +638,7 @@ impl<C: Call> TryCall for MaybeCall<C> {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DynTryCall<C: Call> {
     pub allow_failure: bool,
     pub call: C,
@@ -2,12 +2,13 @@ use core::{
     borrow::Borrow,
     fmt::{self, Debug},
 };
+use std::sync::Arc;
 
 use ethers::{
     abi::AbiError,
-    contract::{ContractInstance, EthCall},
+    contract::{ContractError as RawContractError, ContractInstance, EthCall, FunctionCall},
     providers::Middleware,
-    types::{Address, BlockId, TxHash, U256},
+    types::{Address, BlockId, TransactionReceipt, TxHash, U256},
 };
 use impl_tools::autoimpl;
 
@@ -43,8 +44,18 @@ where
         self
     }
 
-    pub fn priority_fee_per_gas(mut self, priority_fee: impl Into<U256>) -> Self {
-        self.inner = self.inner.priority_fee_per_gas(priority_fee);
+    /// Makes it a legacy transaction or an EIP-1559 one
+    pub fn legacy(mut self, legacy: bool) -> Self {
+        self.inner = self.inner.legacy(legacy);
+        self
+    }
+
+    pub fn fees(
+        mut self,
+        base_fee_per_gas: impl Into<U256>,
+        priority_fee_per_gas: impl Into<U256>,
+    ) -> Self {
+        self.inner = self.inner.fees(base_fee_per_gas, priority_fee_per_gas);
         self
     }
 
@@ -62,6 +73,17 @@ where
         })
     }
 
+    /// Decodes output of the multicall executed elsewhere, e.g. in local EVM
+    pub fn decode_output(
+        &self,
+        output: Result<&[u8], &[u8]>,
+    ) -> Result<Result<C::Ok, MultiCallErrors<C::Reverted>>, AbiError> {
+        Ok(match self.inner.decode_output(output)? {
+            Ok(output) => Ok(C::decode_ok_raw(output, &self.meta)?),
+            Err(reverted) => Err(self.decode_reverted(reverted)?),
+        })
+    }
+
     pub async fn estimate_gas(
         &self,
     ) -> Result<Result<U256, MultiCallErrors<C::Reverted>>, ContractError<M>> {
@@ -97,6 +119,13 @@ where
     pub fn client(&self) -> &M {
         self.0.client_ref()
     }
+
+    pub fn client_owned(&self) -> B
+    where
+        B: Clone,
+    {
+        self.0.client()
+    }
 }
 
 impl<B, M> MultiCallContract<B, M>
@@ -124,14 +153,34 @@ where
         }
     }
 
-    // TODO: other methods
-    // pub async fn owner(&self) -> Result<Address, RawContractError<M>> {
-    //     self.0.owner().await
-    // }
+    pub fn owner(&self) -> FunctionCall<B, M, Address> {
+        self.0
+            .method_hash(<raw::OwnerCall>::selector(), ())
+            .expect("method not found")
+    }
+
+    pub fn transfer_ownership(&self, new_owner: Address) -> FunctionCall<B, M, ()> {
+        self.0
+            .method_hash(<raw::TransferOwnershipCall>::selector(), new_owner)
+            .expect("method not found")
+    }
+}
 
-    // pub async fn transfer_ownership(&self, new_owner: Address) -> Result<(), RawContractError<M>> {
-    //     self.0.transfer_ownership(new_owner).await
-    // }
+impl<M: Middleware> MultiCallContract<Arc<M>, M> {
+    /// Deploys new multicall owned by the sender with legacy or EIP-1559
+    /// transaction and waits for given number of confirmations
+    pub async fn deploy(
+        client: Arc<M>,
+        confirmations: usize,
+        legacy: bool,
+    ) -> Result<(Self, TransactionReceipt), RawContractError<M>> {
+        let mut deployer = raw::MultiCall::deploy(client.clone(), ())?.confirmations(confirmations);
+        if legacy {
+            deployer = deployer.legacy();
+        }
+        let (contract, receipt) = deployer.send_with_receipt().await?;
+        Ok((Self::new(contract.address(), client), receipt))
+    }
 }
 
 impl<B, M> Debug for MultiCallContract<B, M>
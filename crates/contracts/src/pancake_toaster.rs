@@ -6,7 +6,7 @@ tracked_abigen!(
 );
 
 impl EthTypedCall for FrontRunSwapCall {
-    type Ok = FrontRunSwapExtReturn;
+    type Ok = FrontRunSwapReturn;
     type Reverted = PancakeToasterErrors;
 }
 
@@ -21,7 +21,7 @@ use thiserror::Error;
 use tracing::warn;
 
 use crate::{
-    cached::{CachedAt, CachedAtBlock},
+    cached::CachedAt,
     monitors::BlockMonitor,
 };
 
@@ -145,7 +145,7 @@ use self::pending_state::PendingState;
 pub(crate) struct InnerAccount<P: JsonRpcClient, S: Signer> {
     provider: Arc<Provider<P>>,
     signer: S,
-    balance: CachedAtBlock<U256>,
+    balance: CachedAt<H256, U256>,
 }
 
 #[derive(Error, Debug)]
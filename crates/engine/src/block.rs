@@ -1,5 +1,5 @@
 use core::{cmp::Reverse, iter::Map, mem, slice};
-use std::sync::Arc;
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
 
 use ethers::{
     providers::Middleware,
@@ -8,6 +8,8 @@ use ethers::{
 use futures::lock::Mutex;
 use impl_tools::autoimpl;
 use itertools::Itertools;
+use metrics::histogram;
+use serde::{Deserialize, Serialize};
 use sandwitch_contracts::{
     multicall::{
         Call, Calls, DynTryCall, MultiCall, MultiCallContract, MultiCallErrors, MultiFunctionCall,
@@ -16,8 +18,15 @@ use sandwitch_contracts::{
     ContractError,
 };
 use thiserror::Error as ThisError;
-
-use crate::transactions::{InvalidTransaction, Transaction};
+use tokio::time::Instant;
+
+#[cfg(feature = "evm")]
+use crate::evm::Simulator;
+use crate::{
+    config::TxType,
+    monitor::current_monitor,
+    transactions::{InvalidTransaction, Transaction},
+};
 
 #[derive(Debug)]
 #[autoimpl(Deref using self.block)]
@@ -27,6 +36,12 @@ pub struct ProcessingBlock<M, TX = Transaction> {
     account: Address,
     multicall: Arc<MultiCallContract<Arc<M>, M>>,
     first_priority_fee_per_gas: U256,
+    /// Whether candidates are legacy transactions or EIP-1559 ones
+    legacy: bool,
+    /// Views pending transactions were first seen by if several were merged
+    pub(crate) seen_by: HashMap<TxHash, Arc<str>>,
+    #[cfg(feature = "evm")]
+    simulator: Option<Arc<Simulator<M>>>,
 }
 
 impl<M, TX> ProcessingBlock<M, TX> {
@@ -34,6 +49,14 @@ impl<M, TX> ProcessingBlock<M, TX> {
         self.account
     }
 
+    pub fn multicall(&self) -> Address {
+        self.multicall.address()
+    }
+
+    pub fn client(&self) -> Arc<M> {
+        self.multicall.client_owned()
+    }
+
     fn base_fee_per_gas(&self) -> U256 {
         self.block.base_fee_per_gas.unwrap_or(0.into())
     }
@@ -45,6 +68,12 @@ impl<M, TX> ProcessingBlock<M, TX> {
     pub fn first_in_block(&self, calls: impl MultiCall) -> PrioritizedMultiCall {
         PrioritizedMultiCall::new(calls, self.first_priority_fee_per_gas)
     }
+
+    /// Name of the pending view transaction was first seen by,
+    /// known only if pending blocks of several views were merged
+    pub fn seen_by(&self, tx_hash: TxHash) -> Option<&str> {
+        self.seen_by.get(&tx_hash).map(AsRef::as_ref)
+    }
 }
 
 impl<M, TX> ProcessingBlock<M, TX>
@@ -61,7 +90,8 @@ where
                 .multicall
                 .multicall(calls.clone())
                 .block(BlockNumber::Pending)
-                .priority_fee_per_gas(priority_fee_per_gas),
+                .legacy(self.legacy)
+                .fees(self.base_fee_per_gas(), priority_fee_per_gas),
             call: PrioritizedMultiCall::new(calls, priority_fee_per_gas),
             block: &self,
         }
@@ -109,6 +139,10 @@ pub struct PendingBlockFactory<M> {
     account: Address,
     multicall: Arc<MultiCallContract<Arc<M>, M>>,
     fees: Mutex<PriorityFeeEstimator>,
+    tx_type: TxType,
+    /// Chain id to simulate candidates in local EVM with
+    #[cfg(feature = "evm")]
+    evm_chain_id: Option<u64>,
 }
 
 impl<M> PendingBlockFactory<M> {
@@ -117,9 +151,25 @@ impl<M> PendingBlockFactory<M> {
             account,
             multicall: multicall.into(),
             fees: Default::default(),
+            tx_type: Default::default(),
+            #[cfg(feature = "evm")]
+            evm_chain_id: None,
         }
     }
 
+    /// Type of candidate transactions
+    pub fn tx_type(mut self, tx_type: TxType) -> Self {
+        self.tx_type = tx_type;
+        self
+    }
+
+    /// Simulate candidates in local EVM instead of calling the node
+    #[cfg(feature = "evm")]
+    pub fn simulate_locally(mut self, chain_id: u64) -> Self {
+        self.evm_chain_id = Some(chain_id);
+        self
+    }
+
     // pub async fn make_block(&self, block: Block<TxHash>) -> ProcessingBlock<M, TxHash> {}
 
     pub async fn make_pending_block(
@@ -176,47 +226,60 @@ impl<M> PendingBlockFactory<M> {
             })
             .try_collect()?;
 
+        let block = Block {
+            hash,
+            parent_hash,
+            uncles_hash,
+            author,
+            state_root,
+            transactions_root,
+            receipts_root,
+            number,
+            gas_used,
+            gas_limit,
+            extra_data,
+            logs_bloom,
+            timestamp,
+            difficulty,
+            total_difficulty,
+            seal_fields,
+            uncles,
+            transactions,
+            size,
+            mix_hash,
+            nonce,
+            base_fee_per_gas,
+            withdrawals,
+            withdrawals_root,
+            other,
+        };
+
         Ok(ProcessingBlock {
-            first_priority_fee_per_gas: self
-                .fees
-                .lock()
-                .await
-                .estimate(transactions.first().map(|tx| tx.fees.priority_fee())),
-            block: Block {
-                hash,
-                parent_hash,
-                uncles_hash,
-                author,
-                state_root,
-                transactions_root,
-                receipts_root,
-                number,
-                gas_used,
-                gas_limit,
-                extra_data,
-                logs_bloom,
-                timestamp,
-                difficulty,
-                total_difficulty,
-                seal_fields,
-                uncles,
-                transactions,
-                size,
-                mix_hash,
-                nonce,
-                base_fee_per_gas,
-                withdrawals,
-                withdrawals_root,
-                other,
-            },
+            first_priority_fee_per_gas: self.fees.lock().await.estimate(
+                block.transactions.first().map(|tx| {
+                    tx.fees
+                        .effective_priority_fee(block.base_fee_per_gas.unwrap_or_default())
+                }),
+            ),
+            legacy: self.tx_type.is_legacy(block.base_fee_per_gas),
+            #[cfg(feature = "evm")]
+            simulator: self.evm_chain_id.map(|chain_id| {
+                Arc::new(Simulator::new(
+                    self.multicall.client_owned(),
+                    chain_id,
+                    &block,
+                ))
+            }),
+            block,
             to_send: Default::default(),
+            seen_by: Default::default(),
             account: self.account,
             multicall: self.multicall.clone(),
         })
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Serialize, Deserialize)]
 #[autoimpl(Deref<Target = [DynTryCall<RawCall>]> using self.calls)]
 pub struct MultiCallGroups {
     calls: Calls<RawCall>,
@@ -266,11 +329,15 @@ impl IntoIterator for MultiCallGroups {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 #[autoimpl(Deref using self.calls)]
 #[autoimpl(DerefMut using self.calls)]
 pub struct PrioritizedMultiCall {
     pub calls: MultiCallGroups,     // TODO: no pub
     pub priority_fee_per_gas: U256, // TODO: no pub
+    /// Names of monitors these calls were produced by, only
+    /// deserialized ones are owned
+    pub(crate) monitors: Vec<Cow<'static, str>>,
 }
 
 impl PrioritizedMultiCall {
@@ -278,11 +345,16 @@ impl PrioritizedMultiCall {
         Self {
             calls: calls.into(),
             priority_fee_per_gas: priority_fee_per_gas.into(),
+            monitors: Vec::new(),
         }
     }
+
+    pub fn monitors(&self) -> &[Cow<'static, str>] {
+        &self.monitors
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 #[autoimpl(Deref using self.tx)]
 pub struct TxWithLogs {
     pub tx: Transaction,
@@ -300,7 +372,10 @@ impl<'a, M> AdjacentPendingTxsWithLogs<'a, M> {
     fn priority_fee_per_gas(&self) -> U256 {
         self.txs
             .first()
-            .map(|tx| self.block.base_fee_per_gas() + tx.fees.priority_fee())
+            .map(|tx| {
+                tx.fees
+                    .effective_priority_fee(self.block.base_fee_per_gas())
+            })
             .expect("empty continuious transactions")
     }
 
@@ -349,7 +424,19 @@ pub struct ToSend(Mutex<Vec<PrioritizedMultiCall>>);
 
 impl ToSend {
     pub async fn add_to_send(&self, calls: impl IntoIterator<Item = PrioritizedMultiCall>) {
-        self.0.lock().await.extend(calls)
+        let monitor = current_monitor();
+        self.0.lock().await.extend(calls.into_iter().map(|mut call| {
+            call.monitors.extend(monitor.map(Cow::Borrowed));
+            call
+        }))
+    }
+
+    pub fn as_slice(&mut self) -> &[PrioritizedMultiCall] {
+        self.0.get_mut()
+    }
+
+    pub fn retain(&mut self, f: impl FnMut(&PrioritizedMultiCall) -> bool) {
+        self.0.get_mut().retain(f)
     }
 
     pub fn reduce(self) -> Vec<PrioritizedMultiCall> {
@@ -359,15 +446,19 @@ impl ToSend {
             .into_iter()
             .group_by(|t| t.priority_fee_per_gas)
             .into_iter()
-            .filter_map(|(priority_fee_per_gas, group)| {
-                Some(PrioritizedMultiCall {
-                    calls: group.map(|g| g.calls).reduce(|mut r, l| {
-                        r.extend(l.calls.into_iter().map(DynTryCall::into_call));
-                        r
-                    })?,
-                    priority_fee_per_gas,
+            .filter_map(|(_, group)| {
+                group.reduce(|mut r, l| {
+                    r.calls
+                        .extend(l.calls.calls.into_iter().map(DynTryCall::into_call));
+                    r.monitors.extend(l.monitors);
+                    r
                 })
             })
+            .map(|mut p| {
+                p.monitors.sort_unstable();
+                p.monitors.dedup();
+                p
+            })
             .collect();
         calls.sort_unstable_by_key(|p| Reverse(p.priority_fee_per_gas));
         calls
@@ -388,24 +479,71 @@ impl<'a, M, C: MultiCall, TX> From<CandidateToSend<'a, M, C, TX>> for Prioritize
 
 impl<'a, M, C, TX> CandidateToSend<'a, M, C, TX>
 where
-    M: Middleware,
+    M: Middleware + 'static,
     C: MultiCall,
 {
+    fn gas_price(&self) -> U256 {
+        self.block.base_fee_per_gas() + self.call.priority_fee_per_gas
+    }
+
+    /// Duration is recorded by where it is executed, so that local EVM
+    /// can be compared with the node
     pub async fn call(
         &self,
     ) -> Result<Result<C::Ok, MultiCallErrors<C::Reverted>>, ContractError<M>> {
-        self.function_call.call().await
+        let started_at = Instant::now();
+        #[cfg(feature = "evm")]
+        if let Some(simulator) = &self.block.simulator {
+            let simulated = simulator
+                .clone()
+                .simulate(self.function_call.tx(), self.gas_price())
+                .await?;
+            histogram!("sandwitch_call_duration", started_at.elapsed(), "simulation" => "evm");
+            return Ok(self.function_call.decode_output(
+                simulated
+                    .output
+                    .as_deref()
+                    .map_err(|reverted| reverted.as_ref()),
+            )?);
+        }
+        let r = self.function_call.call().await;
+        histogram!("sandwitch_call_duration", started_at.elapsed(), "simulation" => "node");
+        r
     }
 
     // TODO: this is based on this actual block, not latest one
     pub async fn estimate_fee(
         &self,
     ) -> Result<Result<U256, MultiCallErrors<C::Reverted>>, ContractError<M>> {
-        Ok(self
-            .function_call
-            .estimate_gas()
-            .await?
-            .map(|gas| gas * (self.block.base_fee_per_gas() + self.call.priority_fee_per_gas)))
+        let started_at = Instant::now();
+        #[cfg(feature = "evm")]
+        if let Some(simulator) = &self.block.simulator {
+            let simulated = simulator
+                .clone()
+                .simulate(self.function_call.tx(), self.gas_price())
+                .await?;
+            histogram!(
+                "sandwitch_estimate_fee_duration",
+                started_at.elapsed(),
+                "simulation" => "evm"
+            );
+            return Ok(self
+                .function_call
+                .decode_output(
+                    simulated
+                        .output
+                        .as_deref()
+                        .map_err(|reverted| reverted.as_ref()),
+                )?
+                .map(|_| U256::from(simulated.gas_used) * self.gas_price()));
+        }
+        let gas = self.function_call.estimate_gas().await?;
+        histogram!(
+            "sandwitch_estimate_fee_duration",
+            started_at.elapsed(),
+            "simulation" => "node"
+        );
+        Ok(gas.map(|gas| gas * self.gas_price()))
     }
 
     pub async fn add_to_send(self) {
@@ -0,0 +1,261 @@
+use core::time::Duration;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use ethers::types::U256;
+use metrics::{register_counter, register_gauge, Counter, Gauge};
+use serde::Deserialize;
+use serde_with::{serde_as, DurationSeconds};
+use tokio::time::Instant;
+use tracing::{error, info};
+
+use crate::config::Ether;
+
+#[serde_as]
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    /// Trip after this many consecutive reverted or unprofitable transactions
+    pub max_consecutive_failures: Option<usize>,
+
+    /// Trip if cumulative loss within `loss_window` exceeds this amount, in ether
+    #[serde_as(as = "Option<Ether>")]
+    pub max_loss: Option<U256>,
+
+    #[serde(rename = "loss_window_secs")]
+    #[serde_as(as = "DurationSeconds")]
+    pub loss_window: Duration,
+
+    /// Trip if balance of our account or multicall drops by more than
+    /// this amount between two consecutive blocks, in ether
+    #[serde_as(as = "Option<Ether>")]
+    pub max_balance_drop: Option<U256>,
+
+    /// Reset automatically after being tripped for this long,
+    /// otherwise stay tripped until reset manually
+    #[serde(rename = "cool_down_secs")]
+    #[serde_as(as = "Option<DurationSeconds>")]
+    pub cool_down: Option<Duration>,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            max_consecutive_failures: None,
+            max_loss: None,
+            loss_window: Duration::from_secs(60 * 60),
+            max_balance_drop: None,
+            cool_down: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    consecutive_failures: usize,
+    losses: VecDeque<(Instant, U256)>,
+    tripped_at: Option<Instant>,
+}
+
+/// Pauses sending transactions when realized results go bad
+pub struct CircuitBreaker {
+    scope: String,
+    cfg: Arc<CircuitBreakerConfig>,
+    state: Mutex<State>,
+    tripped: Gauge,
+    trips: Counter,
+}
+
+impl CircuitBreaker {
+    fn new(scope: impl Into<String>, cfg: Arc<CircuitBreakerConfig>) -> Self {
+        let scope = scope.into();
+        Self {
+            tripped: register_gauge!("sandwitch_circuit_breaker_tripped", "scope" => scope.clone()),
+            trips: register_counter!("sandwitch_circuit_breaker_trips", "scope" => scope.clone()),
+            scope,
+            cfg,
+            state: Default::default(),
+        }
+    }
+
+    pub fn scope(&self) -> &str {
+        &self.scope
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(tripped_at) = state.tripped_at else {
+            return false;
+        };
+        if self
+            .cfg
+            .cool_down
+            .is_some_and(|cool_down| tripped_at.elapsed() >= cool_down)
+        {
+            info!(scope = %self.scope, "circuit breaker cooled down");
+            self.reset_state(&mut state);
+            return false;
+        }
+        true
+    }
+
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.tripped_at.is_some() {
+            info!(scope = %self.scope, "circuit breaker reset");
+        }
+        self.reset_state(&mut state);
+    }
+
+    fn reset_state(&self, state: &mut State) {
+        *state = State::default();
+        self.tripped.set(0.0);
+    }
+
+    fn trip(&self, state: &mut State, reason: &str) {
+        if state.tripped_at.is_some() {
+            return;
+        }
+        error!(
+            scope = %self.scope,
+            reason,
+            "circuit breaker tripped, pausing sending transactions",
+        );
+        state.tripped_at = Some(Instant::now());
+        self.tripped.set(1.0);
+        self.trips.increment(1);
+    }
+
+    fn on_success(&self) {
+        self.state.lock().unwrap().consecutive_failures = 0;
+    }
+
+    fn on_failure(&self, loss: U256) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        state.consecutive_failures += 1;
+        if self
+            .cfg
+            .max_consecutive_failures
+            .is_some_and(|max| state.consecutive_failures >= max)
+        {
+            let reason = format!("{} consecutive failures", state.consecutive_failures);
+            self.trip(&mut state, &reason);
+        }
+
+        if loss.is_zero() {
+            return;
+        }
+        state.losses.push_back((now, loss));
+        while state
+            .losses
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > self.cfg.loss_window)
+        {
+            state.losses.pop_front();
+        }
+        let total = state
+            .losses
+            .iter()
+            .fold(U256::zero(), |total, (_, loss)| total + loss);
+        if self.cfg.max_loss.is_some_and(|max| total > max) {
+            let reason = format!("lost {total} wei within {:?}", self.cfg.loss_window);
+            self.trip(&mut state, &reason);
+        }
+    }
+
+    fn on_balance_drop(&self, drop: U256) {
+        if self.cfg.max_balance_drop.is_some_and(|max| drop > max) {
+            let reason = format!("balance dropped by {drop} wei");
+            self.trip(&mut self.state.lock().unwrap(), &reason);
+        }
+    }
+}
+
+/// Outcome of our transactions sent together once they got included
+/// into a block, loss is in wei including paid fees
+#[derive(Debug, Clone, Copy)]
+pub enum TxOutcome {
+    Succeeded,
+    Reverted { loss: U256 },
+    Unprofitable { loss: U256 },
+}
+
+/// Global circuit breaker along with the ones for each of monitors,
+/// all sharing the same thresholds
+pub struct CircuitBreakers {
+    cfg: Arc<CircuitBreakerConfig>,
+    global: CircuitBreaker,
+    monitors: Mutex<HashMap<Cow<'static, str>, Arc<CircuitBreaker>>>,
+}
+
+impl CircuitBreakers {
+    pub fn new(cfg: CircuitBreakerConfig) -> Self {
+        let cfg = Arc::new(cfg);
+        Self {
+            global: CircuitBreaker::new("global", cfg.clone()),
+            cfg,
+            monitors: Default::default(),
+        }
+    }
+
+    pub fn global(&self) -> &CircuitBreaker {
+        &self.global
+    }
+
+    pub fn monitor(&self, name: Cow<'static, str>) -> Arc<CircuitBreaker> {
+        self.monitors
+            .lock()
+            .unwrap()
+            .entry(name.clone())
+            .or_insert_with(|| Arc::new(CircuitBreaker::new(name, self.cfg.clone())))
+            .clone()
+    }
+
+    /// Whether any of given monitors has its circuit breaker tripped
+    pub fn is_any_tripped(&self, monitors: &[Cow<'static, str>]) -> bool {
+        monitors
+            .iter()
+            .any(|name| self.monitor(name.clone()).is_tripped())
+    }
+
+    /// Scopes of tripped circuit breakers
+    pub fn tripped(&self) -> Vec<String> {
+        let monitors: Vec<_> = self.monitors.lock().unwrap().values().cloned().collect();
+        [&self.global]
+            .into_iter()
+            .chain(monitors.iter().map(AsRef::as_ref))
+            .filter(|breaker| breaker.is_tripped())
+            .map(|breaker| breaker.scope().to_string())
+            .collect()
+    }
+
+    pub fn reset_all(&self) {
+        self.global.reset();
+        for breaker in self.monitors.lock().unwrap().values() {
+            breaker.reset();
+        }
+    }
+
+    pub fn on_outcome(&self, monitors: &[Cow<'static, str>], outcome: TxOutcome) {
+        let breakers = monitors.iter().map(|name| self.monitor(name.clone()));
+        match outcome {
+            TxOutcome::Succeeded => {
+                self.global.on_success();
+                breakers.for_each(|b| b.on_success());
+            }
+            TxOutcome::Reverted { loss } | TxOutcome::Unprofitable { loss } => {
+                self.global.on_failure(loss);
+                breakers.for_each(|b| b.on_failure(loss));
+            }
+        }
+    }
+
+    pub fn on_balance_drop(&self, drop: U256) {
+        self.global.on_balance_drop(drop);
+    }
+}
@@ -1,6 +1,5 @@
 use std::{collections::HashMap, hash::Hash, sync::Arc};
 
-use ethers::types::H256;
 use futures::{lock::Mutex, Future, TryFuture, TryFutureExt};
 
 #[derive(Default)]
@@ -96,9 +95,14 @@ impl<T> Cached<T> {
     }
 }
 
-#[derive(Default)]
 pub(crate) struct CachedAt<ID, T>(Mutex<HashMap<ID, Arc<Cached<T>>>>);
 
+impl<ID, T> Default for CachedAt<ID, T> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
 impl<ID, T> CachedAt<ID, T>
 where
     ID: Eq + Hash + Clone,
@@ -106,7 +110,6 @@ where
 {
     async fn get_at(&self, at: ID) -> Arc<Cached<T>> {
         let mut m = self.0.lock().await;
-        // TODO: drain old entries
         m.entry(at).or_insert_with(|| Arc::new(None.into())).clone()
     }
 
@@ -122,7 +125,6 @@ where
             .await
     }
 
-    #[allow(dead_code)]
     pub(crate) async fn get_at_or_try_insert_with<F, Fut>(&self, at: ID, f: F) -> Result<T, Fut::Error>
     where
         F: FnOnce(&ID) -> Fut,
@@ -134,7 +136,6 @@ where
             .await
     }
 
-    #[allow(dead_code)]
     pub(crate) async fn retain<F>(&self, mut pred: F)
     where
         F: FnMut(&ID) -> bool,
@@ -151,5 +152,3 @@ where
         m.shrink_to_fit();
     }
 }
-
-pub(crate) type CachedAtBlock<T> = CachedAt<H256, T>;
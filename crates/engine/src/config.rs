@@ -1,8 +1,15 @@
 use core::time::Duration;
 
-use ethers::types::Address;
-use serde::Deserialize;
-use serde_with::{serde_as, DurationMilliSeconds};
+use ethers::{
+    types::{Address, U256},
+    utils::{format_units, parse_units},
+};
+use serde::{de, Deserialize, Deserializer, Serializer};
+use serde_with::{serde_as, DeserializeAs, DurationMilliSeconds, SerializeAs};
+
+use crate::{
+    breaker::CircuitBreakerConfig, limits::SpendingLimitsConfig, recorder::RecorderConfig,
+};
 
 #[serde_as]
 #[derive(Deserialize, Debug)]
@@ -16,4 +23,145 @@ pub struct Config {
     pub tx_propagation_delay: Duration,
 
     pub multicall: Address,
+
+    /// Token profits are taken in, e.g. wrapped native one. Changes of
+    /// its balance of the multicall along with paid fees make outcomes
+    /// of our transactions, only fees are accounted if not set.
+    pub base_token: Option<Address>,
+
+    /// Log errors of processing a block and carry on with the next one
+    /// instead of stopping
+    #[serde(default)]
+    pub skip_failed_blocks: bool,
+
+    #[serde(default)]
+    pub limits: SpendingLimitsConfig,
+
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+
+    /// Record processed blocks and our decisions for offline analysis
+    pub recorder: Option<RecorderConfig>,
+
+    /// Where calls of candidates are executed
+    #[serde(default)]
+    pub simulation: Simulation,
+
+    /// Type of transactions to send
+    #[serde(default)]
+    pub tx_type: TxType,
+
+    /// Time to wait for pending blocks of other views once the first one
+    /// is received, used only if pending views are set
+    #[serde(rename = "pending_views_grace_ms")]
+    #[serde(default = "Config::default_pending_views_grace")]
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub pending_views_grace: Duration,
+}
+
+impl Config {
+    fn default_pending_views_grace() -> Duration {
+        Duration::from_millis(100)
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Simulation {
+    /// `eth_call` and `eth_estimateGas` against pending block of the node
+    #[default]
+    Node,
+    /// Local EVM on top of state prefetched from the node, requires
+    /// `evm` feature, compare `sandwitch_call_duration` before switching
+    Evm,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TxType {
+    /// EIP-1559 if blocks have base fee, legacy otherwise
+    #[default]
+    Auto,
+    Legacy,
+    Eip1559,
+}
+
+impl TxType {
+    /// Whether to send legacy transactions on top of the block
+    /// with given base fee
+    pub fn is_legacy(self, base_fee_per_gas: Option<U256>) -> bool {
+        match self {
+            Self::Auto => base_fee_per_gas.is_none(),
+            Self::Legacy => true,
+            Self::Eip1559 => false,
+        }
+    }
+}
+
+macro_rules! units {
+    ($(#[$meta:meta])* $vis:vis struct $name:ident = $units:literal;) => {
+        $(#[$meta])*
+        $vis struct $name;
+
+        impl<'de> DeserializeAs<'de, U256> for $name {
+            fn deserialize_as<D>(deserializer: D) -> Result<U256, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                #[derive(Deserialize)]
+                #[serde(untagged)]
+                enum Amount {
+                    Integer(u64),
+                    Float(f64),
+                    String(String),
+                }
+
+                let amount = match Amount::deserialize(deserializer)? {
+                    Amount::Integer(v) => v.to_string(),
+                    Amount::Float(v) => v.to_string(),
+                    Amount::String(v) => v,
+                };
+                parse_units(&amount, $units)
+                    .map(Into::into)
+                    .map_err(|err| de::Error::custom(format!("invalid amount '{amount}': {err}")))
+            }
+        }
+
+        impl SerializeAs<U256> for $name {
+            fn serialize_as<S>(source: &U256, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                serializer.serialize_str(
+                    &format_units(*source, $units).map_err(serde::ser::Error::custom)?,
+                )
+            }
+        }
+    };
+}
+
+units! {
+    /// (De)serializes [`U256`] amount of wei given in gwei, e.g. `5` or `"0.1"`
+    pub struct Gwei = "gwei";
+}
+
+units! {
+    /// (De)serializes [`U256`] amount of wei given in ether, e.g. `0.05` or `"1.5"`
+    pub struct Ether = "ether";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tx_type_is_legacy() {
+        assert!(TxType::Auto.is_legacy(None));
+        assert!(!TxType::Auto.is_legacy(Some(0.into())));
+        assert!(!TxType::Auto.is_legacy(Some(10.into())));
+        assert!(TxType::Legacy.is_legacy(None));
+        assert!(TxType::Legacy.is_legacy(Some(10.into())));
+        assert!(!TxType::Eip1559.is_legacy(None));
+        assert!(!TxType::Eip1559.is_legacy(Some(10.into())));
+    }
 }
@@ -0,0 +1,313 @@
+use core::{mem, time::Duration};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+};
+
+use anyhow::anyhow;
+use ethers::{
+    providers::Middleware,
+    signers::{LocalWallet, Signer},
+    types::{Address, BlockNumber, TransactionRequest, TxHash, U256},
+};
+use futures::try_join;
+use serde::Serialize;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use sandwitch_contracts::{retype_tx, set_fees};
+
+use crate::{breaker::CircuitBreakers, config::TxType, limits::SpendingLimits};
+
+/// Change to be applied by the engine before processing next block
+pub type Staged = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Timings {
+    pub block_interval: Duration,
+    pub tx_propagation_delay: Duration,
+}
+
+/// Switches and state of the running engine, which can be
+/// inspected and changed from outside of it
+pub struct Control {
+    paused: AtomicBool,
+    disabled_monitors: RwLock<HashSet<String>>,
+    timings: RwLock<Timings>,
+    /// Applied in order they were staged
+    staged: Mutex<Vec<Staged>>,
+    status: Mutex<Status>,
+    liveness: Mutex<Liveness>,
+    /// Sent transactions which were not included in any block yet
+    pub(crate) in_flight: Mutex<Vec<InFlightTx>>,
+}
+
+impl Control {
+    pub fn new(timings: Timings) -> Self {
+        Self {
+            paused: Default::default(),
+            disabled_monitors: Default::default(),
+            timings: RwLock::new(timings),
+            staged: Default::default(),
+            status: Default::default(),
+            liveness: Default::default(),
+            in_flight: Default::default(),
+        }
+    }
+
+    pub fn timings(&self) -> Timings {
+        *self.timings.read().unwrap()
+    }
+
+    pub fn set_timings(&self, timings: Timings) {
+        *self.timings.write().unwrap() = timings;
+    }
+
+    /// Schedules change to be applied between blocks after the ones
+    /// staged before, so that none of them is lost
+    pub fn stage(&self, change: Staged) {
+        self.staged.lock().unwrap().push(change);
+    }
+
+    pub(crate) fn apply_staged(&self) {
+        let staged = mem::take(&mut *self.staged.lock().unwrap());
+        if staged.is_empty() {
+            return;
+        }
+        let count = staged.len();
+        for change in staged {
+            change();
+        }
+        info!(count, "staged changes applied");
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Stop sending transactions, pending blocks are still processed
+    pub fn pause(&self) {
+        if !self.paused.swap(true, Ordering::SeqCst) {
+            info!("sending paused");
+        }
+    }
+
+    pub fn resume(&self) {
+        if self.paused.swap(false, Ordering::SeqCst) {
+            info!("sending resumed");
+        }
+    }
+
+    /// Calls of disabled monitors are dropped before sending
+    pub fn set_monitor_enabled(&self, name: &str, enabled: bool) {
+        let mut disabled = self.disabled_monitors.write().unwrap();
+        let changed = if enabled {
+            disabled.remove(name)
+        } else {
+            disabled.insert(name.to_string())
+        };
+        if changed {
+            info!(monitor = name, enabled, "monitor toggled");
+        }
+    }
+
+    pub fn disabled_monitors(&self) -> Vec<String> {
+        self.disabled_monitors
+            .read()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Whether any of given monitors is disabled
+    pub fn is_any_disabled(&self, monitors: &[Cow<'static, str>]) -> bool {
+        let disabled = self.disabled_monitors.read().unwrap();
+        monitors.iter().any(|name| disabled.contains(name.as_ref()))
+    }
+
+    pub fn status(&self) -> Status {
+        Status {
+            in_flight: self.in_flight.lock().unwrap().clone(),
+            ..self.status.lock().unwrap().clone()
+        }
+    }
+
+    pub(crate) fn update_status(&self, f: impl FnOnce(&mut Status)) {
+        f(&mut self.status.lock().unwrap())
+    }
+
+    pub fn liveness(&self) -> Liveness {
+        *self.liveness.lock().unwrap()
+    }
+
+    pub(crate) fn update_liveness(&self, f: impl FnOnce(&mut Liveness)) {
+        f(&mut self.liveness.lock().unwrap())
+    }
+}
+
+/// Signs of the engine being stuck
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Liveness {
+    /// Whether subscription to new heads is alive
+    pub subscribed: bool,
+    /// When last head was received
+    pub last_head_at: Option<Instant>,
+    /// Since when blocks are skipped because our previous
+    /// transactions are still pending
+    pub nonce_stuck_since: Option<Instant>,
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct Status {
+    /// Last head we have observed
+    pub latest_block: Option<u64>,
+    /// Last pending block processed by monitors
+    pub last_processed_block: Option<u64>,
+    /// Nonce of our account at latest block
+    pub next_nonce: Option<U256>,
+    /// Balance of our account at latest block
+    pub balance: Option<U256>,
+    /// Balance of multicall at latest block
+    pub multicall_balance: Option<U256>,
+    pub in_flight: Vec<InFlightTx>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct InFlightTx {
+    pub hash: TxHash,
+    /// Transaction is forgotten once this nonce is used by another one
+    pub nonce: U256,
+    /// Pending block the transaction was sent for, outcome of front and
+    /// back runs sent for the same one is accounted as a whole
+    pub sent_for: Option<u64>,
+    pub monitors: Vec<Cow<'static, str>>,
+}
+
+/// Cloneable handle to control the engine while it is running
+pub struct EngineHandle<M> {
+    pub(crate) client: Arc<M>,
+    pub(crate) wallet: Option<LocalWallet>,
+    pub(crate) account: Address,
+    pub(crate) tx_type: TxType,
+    pub(crate) control: Arc<Control>,
+    pub(crate) limits: Arc<SpendingLimits>,
+    pub(crate) breakers: Arc<CircuitBreakers>,
+}
+
+impl<M> Clone for EngineHandle<M> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            wallet: self.wallet.clone(),
+            account: self.account,
+            tx_type: self.tx_type,
+            control: self.control.clone(),
+            limits: self.limits.clone(),
+            breakers: self.breakers.clone(),
+        }
+    }
+}
+
+impl<M> EngineHandle<M> {
+    pub fn control(&self) -> &Control {
+        &self.control
+    }
+
+    pub fn limits(&self) -> &SpendingLimits {
+        &self.limits
+    }
+
+    pub fn circuit_breakers(&self) -> &CircuitBreakers {
+        &self.breakers
+    }
+}
+
+impl<M> EngineHandle<M>
+where
+    M: Middleware,
+    M::Error: 'static,
+{
+    /// Replaces the first of our transactions which are still pending with
+    /// an empty transfer to ourselves outbidding it by 12.5%, but paying at
+    /// least the current gas price. Returns `None` if there is nothing to cancel.
+    pub async fn cancel_stuck_tx(&self) -> anyhow::Result<Option<TxHash>> {
+        let wallet = self
+            .wallet
+            .as_ref()
+            .ok_or_else(|| anyhow!("unable to sign: wallet is not set"))?;
+        let (nonce, pending_nonce, gas_price, head) = try_join!(
+            self.client
+                .get_transaction_count(self.account, Some(BlockNumber::Latest.into())),
+            self.client
+                .get_transaction_count(self.account, Some(BlockNumber::Pending.into())),
+            self.client.get_gas_price(),
+            self.client.get_block(BlockNumber::Latest),
+        )?;
+        if pending_nonce <= nonce {
+            return Ok(None);
+        }
+        // the last one sent with this nonce is the one to outbid
+        let stuck = self
+            .control
+            .in_flight
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|tx| tx.nonce == nonce)
+            .map(|tx| tx.hash);
+        let stuck_price = match stuck {
+            Some(hash) => self
+                .client
+                .get_transaction(hash)
+                .await?
+                .and_then(|tx| tx.max_fee_per_gas.or(tx.gas_price)),
+            None => None,
+        };
+        // unknown price of the stuck transaction is assumed to be the current one
+        let gas_price = replacement_price(stuck_price.unwrap_or(gas_price), gas_price);
+
+        let tx = TransactionRequest::new()
+            .from(self.account)
+            .to(self.account)
+            .value(0)
+            .nonce(nonce)
+            .gas(21_000);
+        let legacy = self
+            .tx_type
+            .is_legacy(head.and_then(|head| head.base_fee_per_gas));
+        let mut tx = retype_tx(&tx.into(), legacy);
+        // gas price already includes the base fee, so it is paid as a whole,
+        // which also bumps priority fee of the stuck transaction
+        set_fees(&mut tx, U256::zero(), gas_price);
+        tx.set_chain_id(wallet.chain_id());
+        let signature = wallet.sign_transaction_sync(&tx)?;
+
+        warn!(%nonce, %gas_price, "cancelling stuck transaction...");
+        let hash = self
+            .client
+            .send_raw_transaction(tx.rlp_signed(&signature))
+            .await?
+            .tx_hash();
+        self.control.in_flight.lock().unwrap().push(InFlightTx {
+            hash,
+            nonce,
+            sent_for: None,
+            monitors: Vec::new(),
+        });
+        info!(?hash, %nonce, "stuck transaction replaced");
+        Ok(Some(hash))
+    }
+}
+
+/// Price of a replacement of `stuck` transaction, which nodes accept only
+/// if both fees are bumped by at least 10%, not less than `current` one
+fn replacement_price(stuck: U256, current: U256) -> U256 {
+    // 1.125 times rounded up
+    ((stuck * 9 + 7) / 8).max(current)
+}
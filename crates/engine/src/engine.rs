@@ -1,26 +1,32 @@
-use core::{any, pin::pin};
-use std::sync::Arc;
+use core::{any, mem, pin::pin};
+use std::{
+    borrow::{Borrow, Cow},
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
 use anyhow::{anyhow, Context};
 use ethers::{
     providers::{JsonRpcClient, Middleware, Provider, ProviderError, PubsubClient},
     signers::{LocalWallet, Signer},
     types::{
-        transaction::eip2718::TypedTransaction, Address, Block, BlockNumber, Filter, TxHash, U256,
+        transaction::eip2718::TypedTransaction, Address, Block, BlockNumber, Filter, Transaction,
+        TransactionReceipt, TransactionRequest, TxHash, H256, U256,
     },
+    utils::keccak256,
 };
 use futures::{
-    future::{self, Aborted, Fuse, FusedFuture, Future, FutureExt},
+    future::{self, Aborted, Fuse, FusedFuture, Future, FutureExt, TryFutureExt},
     select_biased,
     stream::{FusedStream, FuturesOrdered, FuturesUnordered, StreamExt, TryStreamExt},
     try_join, Stream,
 };
 
-// use metrics::{register_counter, register_gauge, register_histogram, Counter, Histogram};
+use metrics::{register_counter, register_histogram, Counter, Histogram};
 
 use tokio::{
     self,
-    time::{sleep_until, timeout_at, Duration, Instant},
+    time::{sleep, sleep_until, timeout_at, Duration, Instant},
 };
 use tokio_util::sync::CancellationToken;
 use tracing::{
@@ -29,17 +35,24 @@ use tracing::{
 
 use sandwitch_contracts::{
     multicall::{Call, GetBalanceOf, MultiCall, MultiCallContract, MustCall},
-    ContractError, EthTypedCall,
+    retype_tx, set_fees, ContractError, EthTypedCall,
 };
 
 use crate::{
     abort::FutureExt as AbortFutureExt,
-    block::{PendingBlock, PendingBlockFactory, PrioritizedMultiCall, ProcessingBlock},
-    config::Config,
+    block::{
+        PendingBlock, PendingBlockFactory, PrioritizedMultiCall, ProcessingBlock, TxWithLogs,
+    },
+    breaker::{CircuitBreakers, TxOutcome},
+    config::{Config, Simulation, TxType},
+    control::{Control, EngineHandle, InFlightTx, Timings},
+    deadline::with_deadline,
+    limits::{max_fee, SpendingLimits},
     monitor::BlockMonitor,
+    pending::{self, PendingView, ViewMetrics},
     providers::LatencyProvider,
+    recorder::{RecordRef, Recorder, ReducedTx},
     timed::StreamExt as TimedStreamExt,
-    transactions::TransactionRequest,
 };
 
 // TODO: use Signer Middleware
@@ -56,8 +69,21 @@ where
     wallet: Option<LocalWallet>,
     pending_block_factory: PendingBlockFactory<MiddlewareStack<P>>,
     // next_block_at_estimator: NextBlockAtEstimator,
-    tx_propagation_delay: Duration, // TODO: move into next block at estimator
-    block_interval: Duration,
+    skip_failed_blocks: bool,
+    tx_type: TxType,
+    /// Token profits are taken in
+    base_token: Option<Address>,
+    limits: Arc<SpendingLimits>,
+    breakers: Arc<CircuitBreakers>,
+    control: Arc<Control>,
+    /// Balances of our account and multicall at last observed block
+    last_balances: Mutex<Option<(U256, U256)>>,
+    recorder: Option<Recorder>,
+    /// Pending blocks are merged from these instead of taken from client
+    pending_views: Vec<Arc<dyn PendingView>>,
+    pending_views_grace: Duration,
+    view_metrics: Mutex<ViewMetrics>,
+    metrics: Metrics,
     monitor: M,
 }
 
@@ -94,23 +120,88 @@ where
             warn!("node is not mining");
         }
 
+        let pending_block_factory =
+            PendingBlockFactory::new(owner, multicall.clone()).tx_type(cfg.tx_type);
+        let pending_block_factory = match cfg.simulation {
+            Simulation::Node => pending_block_factory,
+            #[cfg(feature = "evm")]
+            Simulation::Evm => pending_block_factory.simulate_locally(
+                client
+                    .get_chainid()
+                    .await
+                    .context("failed to get chain id")?
+                    .as_u64(),
+            ),
+            #[cfg(not(feature = "evm"))]
+            Simulation::Evm => {
+                return Err(anyhow!("simulation in local EVM requires `evm` feature"))
+            }
+        };
+
         Ok(Self {
             client,
             address: owner,
             wallet,
-            pending_block_factory: PendingBlockFactory::new(owner, multicall.clone()),
+            pending_block_factory,
             multicall,
             // next_block_at_estimator: NextBlockAtEstimator::new(cfg.block_interval),
-            tx_propagation_delay: cfg.tx_propagation_delay,
-            block_interval: cfg.block_interval,
+            skip_failed_blocks: cfg.skip_failed_blocks,
+            tx_type: cfg.tx_type,
+            base_token: cfg.base_token,
+            limits: Arc::new(SpendingLimits::new(cfg.limits)),
+            breakers: Arc::new(CircuitBreakers::new(cfg.circuit_breaker)),
+            control: Arc::new(Control::new(Timings {
+                block_interval: cfg.block_interval,
+                tx_propagation_delay: cfg.tx_propagation_delay,
+            })),
+            last_balances: Default::default(),
+            pending_views: Vec::new(),
+            pending_views_grace: cfg.pending_views_grace,
+            view_metrics: Default::default(),
+            metrics: Default::default(),
+            recorder: cfg
+                .recorder
+                .map(Recorder::new)
+                .transpose()
+                .context("failed to initialize recorder")?,
             monitor,
         })
     }
 
+    /// Merge pending blocks of given views instead of taking the one
+    /// of the client, which is still used if none of views responds
+    pub fn with_pending_views(
+        mut self,
+        views: impl IntoIterator<Item = Arc<dyn PendingView>>,
+    ) -> Self {
+        self.pending_views = views.into_iter().collect();
+        self
+    }
+
     pub fn account(&self) -> Address {
         self.address
     }
 
+    pub fn circuit_breakers(&self) -> Arc<CircuitBreakers> {
+        self.breakers.clone()
+    }
+
+    pub fn control(&self) -> Arc<Control> {
+        self.control.clone()
+    }
+
+    pub fn handle(&self) -> EngineHandle<MiddlewareStack<P>> {
+        EngineHandle {
+            client: self.client.clone(),
+            wallet: self.wallet.clone(),
+            account: self.address,
+            tx_type: self.tx_type,
+            control: self.control.clone(),
+            limits: self.limits.clone(),
+            breakers: self.breakers.clone(),
+        }
+    }
+
     pub async fn run(self, cancel: CancellationToken) -> anyhow::Result<()> {
         let mut send_txs = FuturesUnordered::new();
 
@@ -124,11 +215,13 @@ where
                 .with_context(|| "failed to subscribe to new blocks")?
                 .fuse()
                 .timed();
+            self.control.update_liveness(|l| l.subscribed = true);
             debug!("listening to new blocks");
 
             let mut process_pending_block = pin!(Fuse::terminated());
 
-            let mut next_block_at_estimator = NextBlockAtEstimator::new(self.block_interval);
+            let mut next_block_at_estimator = NextBlockAtEstimator::new(self.control.timings().block_interval);
+            let mut last_head_at: Option<Instant> = None;
 
             macro_rules! break_err {
                 ($result:expr) => {
@@ -151,6 +244,11 @@ where
                     },
                     (block, received_at) = blocks.select_next_some() => Self::new_head_span(&block).in_scope(|| {
                         debug!("new head received");
+                        self.control.update_liveness(|l| l.last_head_at = Some(received_at));
+                        self.metrics.new_head(
+                            &block,
+                            last_head_at.replace(received_at).map(|at| received_at - at),
+                        );
 
                         if !process_pending_block.is_terminated() {
                             warn!("new head came too early, \
@@ -165,34 +263,48 @@ where
                             return;
                         }
 
+                        // nothing is being processed or sent now, so it's safe to change settings
+                        self.control.apply_staged();
+                        let timings = self.control.timings();
+                        next_block_at_estimator.set_block_interval(timings.block_interval);
+
                         let deadline =
                             next_block_at_estimator.estimate_next_block_at(received_at)
                             - self.latency() // reserve time to send txs to the node
-                            - self.tx_propagation_delay; // reserve time for txs to propagate through the network
+                            - timings.tx_propagation_delay; // reserve time for txs to propagate through the network
 
                         process_pending_block.set(
                             // don't send txs the after deadline even if new head hasn't been received
                             timeout_at(
                                 deadline,
-                                self.delayed_process_pending_block(block, deadline).in_current_span(),
+                                with_deadline(
+                                    deadline,
+                                    self.delayed_process_pending_block(block, deadline),
+                                )
+                                .in_current_span(),
                             )
                             .fuse(),
                         );
                     }),
                     to_send = &mut process_pending_block => match to_send {
+                        Ok(Err(err)) if self.skip_failed_blocks => {
+                            error!(%err, "failed to process pending block, skipping...");
+                        },
                         Ok(to_send) => if let Some(to_send) = break_err!(to_send) {
                             // TODO: debug! sending txs...
                             send_txs.extend(to_send);
                         },
                         Err(elapsed) => {
                             warn!("{elapsed}");
+                            self.metrics.deadline_misses.increment(1);
                             continue;
                         },
                     },
-                    complete => return Ok(()),
+                    complete => break Ok(()),
                 };
             }
         };
+        self.control.update_liveness(|l| l.subscribed = false);
 
         if send_txs.is_empty() {
             return r;
@@ -249,43 +361,189 @@ where
         Ok(Some(nonce_at_block))
     }
 
-    // #[instrument(skip_all, fields(block.number = block_number))]
-    // async fn get_my_balance_at(&self, block_number: u64) -> Result<U256, ProviderError> {
-    //     self.client
-    //         .get_balance(self.account(), Some(block_number.into()))
-    //         .await
-    //     // .map_err(Into::into)
-    // }
-
-    // async fn get_my_and_multicall_balance_at(
-    //     &self,
-    //     block_number: u64,
-    // ) -> anyhow::Result<(U256, U256)> {
-    //     let a = self
-    //         .multicall
-    //         .multicall((GetBalanceOf::MsgSender.must(), GetBalanceOf::This.must()))
-    //         .block(block_number)
-    //         .call()
-    //         .await??;
-    //     Ok(())
-    // }
+    #[instrument(skip_all, fields(block.number = block_number))]
+    async fn get_my_and_multicall_balance_at(
+        &self,
+        block_number: u64,
+    ) -> Result<(U256, U256), ProviderError> {
+        try_join!(
+            self.client
+                .get_balance(self.account(), Some(block_number.into())),
+            self.client
+                .get_balance(self.multicall.address(), Some(block_number.into())),
+        )
+    }
+
+    /// Feeds circuit breakers with outcomes of our transactions included
+    /// into given block and with changes of our balances since last one
+    #[instrument(skip_all, fields(block.number = block_number), err)]
+    async fn observe_block(&self, block_number: u64) -> anyhow::Result<()> {
+        let in_flight = self.control.in_flight.lock().unwrap().clone();
+        let (receipts, (my_balance, multicall_balance), nonce) = try_join!(
+            in_flight
+                .iter()
+                .map(|tx| self.client.get_transaction_receipt(tx.hash))
+                .collect::<FuturesOrdered<_>>()
+                .try_collect::<Vec<_>>(),
+            self.get_my_and_multicall_balance_at(block_number),
+            self.client
+                .get_transaction_count(self.account(), Some(block_number.into())),
+        )?;
+
+        let last_balances = self
+            .last_balances
+            .lock()
+            .unwrap()
+            .replace((my_balance, multicall_balance));
+        self.control.update_status(|status| {
+            status.latest_block = Some(block_number);
+            status.balance = Some(my_balance);
+            status.multicall_balance = Some(multicall_balance);
+        });
+
+        let mut included = Vec::new();
+        let mut done = Vec::new();
+        for (tx, receipt) in in_flight.into_iter().zip(receipts) {
+            match receipt {
+                Some(receipt) => {
+                    done.push(tx.hash);
+                    included.push((tx, receipt));
+                }
+                // nonce was used by another transaction, e.g. the one
+                // cancelling it, so this one will never be included
+                None if tx.nonce < nonce => {
+                    debug!(
+                        tx_hash = ?tx.hash,
+                        nonce = %tx.nonce,
+                        "transaction replaced or dropped"
+                    );
+                    done.push(tx.hash);
+                }
+                None => {}
+            }
+        }
+        self.control
+            .in_flight
+            .lock()
+            .unwrap()
+            .retain(|tx| !done.contains(&tx.hash));
+
+        // front and back runs sent for the same pending block are accounted together
+        let mut bundles: Vec<(Vec<&InFlightTx>, Vec<&TransactionReceipt>)> = Vec::new();
+        for (tx, receipt) in &included {
+            self.metrics.included(receipt);
+            match bundles
+                .iter_mut()
+                .find(|(txs, _)| tx.sent_for.is_some() && txs[0].sent_for == tx.sent_for)
+            {
+                Some((txs, receipts)) => {
+                    txs.push(tx);
+                    receipts.push(receipt);
+                }
+                None => bundles.push((vec![tx], vec![receipt])),
+            }
+        }
+        for (txs, receipts) in bundles {
+            let outcome = outcome(self.multicall.address(), self.base_token, &receipts);
+            let mut monitors: Vec<_> = txs.iter().flat_map(|tx| tx.monitors.clone()).collect();
+            monitors.sort_unstable();
+            monitors.dedup();
+            if !matches!(outcome, TxOutcome::Succeeded) {
+                let tx_hashes: Vec<_> = txs.iter().map(|tx| tx.hash).collect();
+                warn!(?tx_hashes, ?outcome, "our transactions failed");
+            }
+            self.breakers.on_outcome(&monitors, outcome);
+        }
+
+        if let Some((last_my_balance, last_multicall_balance)) = last_balances {
+            for (last, current) in [
+                (last_my_balance, my_balance),
+                (last_multicall_balance, multicall_balance),
+            ] {
+                if last > current {
+                    self.breakers.on_balance_drop(last - current);
+                }
+            }
+        }
+        Ok(())
+    }
 
     #[instrument(skip_all, err)]
     async fn get_pending_block(&self) -> anyhow::Result<PendingBlock<MiddlewareStack<P>>> {
+        let started_at = Instant::now();
         let log_filter = Filter::new().select(BlockNumber::Pending);
-        let (block, logs) = try_join!(
-            self.client.get_block_with_txs(BlockNumber::Pending),
+        let (merged, logs) = try_join!(
+            self.get_merged_pending_block().map(Ok::<_, ProviderError>),
             future::ok([])
             // self.client.get_logs(&log_filter),
         )?;
+        let (block, seen_by) = match merged {
+            Some(merged) => merged,
+            None => (
+                self.client.get_block_with_txs(BlockNumber::Pending).await?,
+                HashMap::new(),
+            ),
+        };
         let Some(block) = block else {
             error!("pending block doest not exist");
             return Err(ProviderError::UnsupportedRPC.into());
         };
-        self.pending_block_factory
+        let mut pending_block = self
+            .pending_block_factory
             .make_pending_block(block, logs)
-            .await
-            .map_err(Into::into)
+            .await?;
+        pending_block.seen_by = seen_by;
+        self.metrics
+            .pending_block_resolved(&pending_block.block, started_at.elapsed());
+        Ok(pending_block)
+    }
+
+    /// Requests pending blocks of all views and merges ones received
+    /// until the grace period after the first one ends.
+    /// Returns [`None`] if none of views has responded.
+    async fn get_merged_pending_block(
+        &self,
+    ) -> Option<(Option<Block<Transaction>>, HashMap<TxHash, Arc<str>>)> {
+        if self.pending_views.is_empty() {
+            return None;
+        }
+        let mut requests: FuturesUnordered<_> = self
+            .pending_views
+            .iter()
+            .map(|view| view.pending_block().map(move |block| (view, block)))
+            .collect();
+
+        let mut blocks = Vec::with_capacity(self.pending_views.len());
+        let mut grace = pin!(Fuse::terminated());
+        loop {
+            select_biased! {
+                () = grace => break,
+                received = requests.next() => match received {
+                    Some((view, Ok(Some(block)))) => {
+                        if blocks.is_empty() {
+                            grace.set(sleep(self.pending_views_grace).fuse());
+                        }
+                        blocks.push((Arc::from(view.name()), block));
+                    }
+                    Some((view, Ok(None))) => {
+                        warn!(view = view.name(), "pending block does not exist");
+                    }
+                    Some((view, Err(err))) => {
+                        warn!(view = view.name(), "failed to get pending block: {err:#}");
+                    }
+                    None => break,
+                },
+            }
+        }
+        let received = blocks.len();
+        let (block, seen_by) =
+            pending::merge(blocks, &mut self.view_metrics.lock().unwrap())?;
+        debug!(
+            views = received,
+            txs = block.transactions.len(),
+            "pending blocks merged"
+        );
+        Some((Some(block), seen_by))
     }
 
     fn latency(&self) -> Duration {
@@ -304,10 +562,13 @@ where
 
         let latest_block_number = latest_block.number.unwrap().as_u64();
 
-        let (next_nonce, pending_block) = try_join!(
+        let (next_nonce, (), mut pending_block) = try_join!(
             self.get_next_nonce_at(latest_block_number),
             // self.monitor.process_block()
-            // self.get_my_balance_at(latest_block_number),
+            // failure is logged and the block is processed anyway, since
+            // in-flight transactions are looked up again on the next one
+            self.observe_block(latest_block_number)
+                .map(|_| anyhow::Ok(())),
             async {
                 sleep_until(deadline - Duration::from_secs(3))
                     .instrument(info_span!("wait_before_request_pending"))
@@ -316,6 +577,14 @@ where
                 self.get_pending_block().await
             }
         )?;
+        self.control
+            .update_status(|status| status.next_nonce = next_nonce);
+        self.control.update_liveness(|l| match next_nonce {
+            Some(_) => l.nonce_stuck_since = None,
+            None => {
+                l.nonce_stuck_since.get_or_insert_with(Instant::now);
+            }
+        });
 
         let Some(next_nonce) = next_nonce else {
             return Ok(None);
@@ -333,8 +602,19 @@ where
             // return Ok(None);
         }
 
+        let segment = self
+            .recorder
+            .as_ref()
+            .and_then(|r| r.segment(pending_block.number.unwrap().as_u64()));
+        if let Some(segment) = &segment {
+            segment.record(&RecordRef::PendingBlock {
+                block: &pending_block.block,
+            });
+        }
+
         debug!("processing pending block");
         // TODO: maybe force sleep until abort_processing_at, so we would send just at the end of the block?
+        let started_at = Instant::now();
         match timeout_at(
             deadline - self.latency(), // reserve time to estimate gas for all produced txs
             self.monitor.process_pending_block(&pending_block),
@@ -344,36 +624,118 @@ where
             Ok(v) => v?,
             Err(elapsed) => {
                 warn!("{elapsed}");
+                self.metrics.deadline_misses.increment(1);
                 return Ok(None);
             }
         }
+        self.metrics
+            .process_block_duration
+            .record(started_at.elapsed());
         debug!("pending block processed");
-        // TODO: log to_send count
+        self.control.update_status(|status| {
+            status.last_processed_block = pending_block.number.map(|n| n.as_u64())
+        });
+        self.metrics
+            .candidates
+            .increment(pending_block.to_send.as_slice().len() as u64);
+
+        if let Some(segment) = &segment {
+            segment.record(&RecordRef::Produced {
+                calls: pending_block.to_send.as_slice(),
+            });
+        }
 
-        let to_send = self.extract_txs_to_send(pending_block, next_nonce).await?;
+        // monitors with tripped circuit breakers or disabled ones keep observing,
+        // but we don't send their calls
+        pending_block.to_send.retain(|p| {
+            !self.breakers.is_any_tripped(p.monitors())
+                && !self.control.is_any_disabled(p.monitors())
+        });
+        // checked before estimating gas, which is pointless if nothing is sent
+        if self.breakers.global().is_tripped() {
+            warn!("circuit breaker is tripped, not sending transactions");
+            return Ok(None);
+        }
+        if self.control.is_paused() {
+            warn!("sending is paused, not sending transactions");
+            return Ok(None);
+        }
 
         let Some(wallet) = &self.wallet else {
             warn!("unable to sign: wallet is not set");
             return Ok(None);
         };
 
+        let sent_for = pending_block.number.map(|number| number.as_u64());
+        let to_send = self.extract_txs_to_send(pending_block).await?;
+        let to_send = self.limits.filter(to_send);
+        if let Some(segment) = &segment {
+            segment.record(&RecordRef::Reduced {
+                txs: to_send
+                    .iter()
+                    .map(|TxToSend { tx, monitors }| ReducedTx {
+                        tx: tx.clone(),
+                        monitors: monitors.iter().map(ToString::to_string).collect(),
+                    })
+                    .collect(),
+            });
+        }
+
         Ok(Some(
             to_send
                 .into_iter()
-                .map(|mut tx| {
+                .enumerate()
+                .map(|(i, TxToSend { mut tx, monitors })| {
+                    // assign nonces only after filtering, so that dropped txs leave no gaps
+                    let nonce = next_nonce + i;
+                    tx.set_nonce(nonce);
                     tx.set_chain_id(wallet.chain_id());
                     let signature = wallet.sign_transaction_sync(&tx)?;
-                    anyhow::Ok(tx.rlp_signed(&signature))
+                    anyhow::Ok((tx.rlp_signed(&signature), nonce, max_fee(&tx), monitors))
                     // TODO: debug! signed
                 })
                 .try_collect::<Vec<_>>()?
                 .into_iter()
-                .map(move |tx| {
+                .map(move |(tx, nonce, fee, monitors)| {
+                    let segment = segment.clone();
+                    let span = info_span!(
+                        parent: &span,
+                        "send_tx",
+                        tx.hash = ?TxHash::from(keccak256(&tx)),
+                        ?monitors,
+                    );
                     async move {
-                        let pending_tx = self.client.send_raw_transaction(tx).await?;
-                        Ok(pending_tx.tx_hash())
-                    } // TODO: instrument from, gas, nonce, tx_hash, gas_price?
-                    .instrument(span.clone())
+                        let sent = self
+                            .client
+                            .send_raw_transaction(tx.clone())
+                            .await
+                            .map(|pending_tx| pending_tx.tx_hash());
+                        if let Some(segment) = segment {
+                            segment.record(&RecordRef::Sent {
+                                raw: tx,
+                                result: sent.as_ref().copied().map_err(ToString::to_string),
+                            });
+                        }
+                        match &sent {
+                            Ok(_) => self.metrics.sent_txs.increment(1),
+                            Err(_) => self.metrics.failed_txs.increment(1),
+                        }
+                        let hash = sent?;
+                        // fees of transactions failed to be sent are never paid
+                        self.limits.charge(fee);
+                        self.control
+                            .in_flight
+                            .lock()
+                            .unwrap()
+                            .push(InFlightTx {
+                                hash,
+                                nonce,
+                                sent_for,
+                                monitors,
+                            });
+                        Ok(hash)
+                    }
+                    .instrument(span)
                 }),
         ))
     }
@@ -382,18 +744,15 @@ where
     async fn extract_txs_to_send(
         &self,
         processed_block: PendingBlock<MiddlewareStack<P>>,
-        mut next_nonce: U256,
-    ) -> anyhow::Result<Vec<TypedTransaction>> {
+    ) -> anyhow::Result<Vec<TxToSend>> {
         processed_block
             .to_send
             .reduce()
             .into_iter()
-            .map(|p| {
-                self.make_tx(p, &processed_block.block, {
-                    let nonce = next_nonce;
-                    next_nonce += 1.into();
-                    nonce
-                })
+            .map(|mut p| {
+                let monitors = mem::take(&mut p.monitors);
+                self.make_tx(p, &processed_block.block)
+                    .map_ok(|tx| TxToSend { tx, monitors })
             })
             .collect::<FuturesOrdered<_>>()
             .try_collect()
@@ -405,30 +764,14 @@ where
         &self,
         p: PrioritizedMultiCall,
         block: &Block<TX>,
-        nonce: impl Into<U256>,
     ) -> anyhow::Result<TypedTransaction> {
-        // TODO: value?
-        let mut tx = TransactionRequest::default()
-            .from(self.account())
-            .to(self.multicall.address())
-            .data({
-                let (raw, _meta) = p.calls.into_inner().encode_raw_calls();
-                raw.encode_calldata()
-            })
-            .nonce(nonce);
-
-        #[cfg(not(feature = "legacy"))]
-        {
-            tx = tx
-                .max_priority_fee_per_gas(p.priority_fee_per_gas)
-                .max_fee_per_gas(block.base_fee_per_gas.unwrap() + p.priority_fee_per_gas);
-        }
-        #[cfg(feature = "legacy")]
-        {
-            tx = tx.gas_price(p.priority_fee_per_gas);
-        }
-
-        let mut tx: TypedTransaction = tx.into();
+        let mut tx = build_tx(
+            self.tx_type,
+            self.account(),
+            self.multicall.address(),
+            p,
+            block.base_fee_per_gas,
+        );
         tx.set_gas(
             self.client
                 .estimate_gas(&tx, Some(BlockNumber::Pending.into()))
@@ -438,6 +781,78 @@ where
     }
 }
 
+/// Transaction of the calls to the multicall paying their priority fee on
+/// top of the base fee of the block, which also decides the type of it
+fn build_tx(
+    tx_type: TxType,
+    from: Address,
+    multicall: Address,
+    p: PrioritizedMultiCall,
+    base_fee_per_gas: Option<U256>,
+) -> TypedTransaction {
+    // TODO: value?
+    let tx = TransactionRequest::new().from(from).to(multicall).data({
+        let (raw, _meta) = p.calls.into_inner().encode_raw_calls();
+        raw.encode_calldata()
+    });
+    let mut tx = retype_tx(&tx.into(), tx_type.is_legacy(base_fee_per_gas));
+    set_fees(
+        &mut tx,
+        base_fee_per_gas.unwrap_or_default(),
+        p.priority_fee_per_gas,
+    );
+    tx
+}
+
+/// Outcome of our transactions sent for the same pending block, i.e. of
+/// front and back runs, by their own effect: change of base token balance
+/// of the multicall minus fees we paid for them
+fn outcome(
+    multicall: Address,
+    base_token: Option<Address>,
+    receipts: &[&TransactionReceipt],
+) -> TxOutcome {
+    let transfer = H256::from(keccak256("Transfer(address,address,uint256)"));
+    let (mut gained, mut spent) = (U256::zero(), U256::zero());
+    for receipt in receipts {
+        spent +=
+            receipt.gas_used.unwrap_or_default() * receipt.effective_gas_price.unwrap_or_default();
+        for log in &receipt.logs {
+            if Some(log.address) != base_token || log.topics.len() != 3 || log.topics[0] != transfer
+            {
+                continue;
+            }
+            let (from, to) = (Address::from(log.topics[1]), Address::from(log.topics[2]));
+            let amount = U256::from_big_endian(&log.data);
+            if to == multicall {
+                gained += amount;
+            }
+            if from == multicall {
+                spent += amount;
+            }
+        }
+    }
+    let reverted = receipts
+        .iter()
+        .any(|receipt| receipt.status.is_some_and(|status| status.is_zero()));
+    match spent.checked_sub(gained).filter(|loss| !loss.is_zero()) {
+        Some(loss) if reverted => TxOutcome::Reverted { loss },
+        Some(loss) => TxOutcome::Unprofitable { loss },
+        None => TxOutcome::Succeeded,
+    }
+}
+
+struct TxToSend {
+    tx: TypedTransaction,
+    monitors: Vec<Cow<'static, str>>,
+}
+
+impl Borrow<TypedTransaction> for TxToSend {
+    fn borrow(&self) -> &TypedTransaction {
+        &self.tx
+    }
+}
+
 // struct BlockData {
 //     my_balance: U256,
 //     multicall_balance: U256,
@@ -453,92 +868,206 @@ impl NextBlockAtEstimator {
         Self { block_interval }
     }
 
+    pub fn set_block_interval(&mut self, block_interval: Duration) {
+        self.block_interval = block_interval;
+    }
+
     pub fn estimate_next_block_at(&mut self, received_at: Instant) -> Instant {
         received_at + self.block_interval
     }
 }
 
-// struct Metrics {
-//     seen_txs: Counter,
-//     resolve_tx_duration: Histogram,
-//     valid_txs: Counter,
-//     resolved_as_pendning_txs: Counter,
-//     process_tx_duration: Histogram,
-//     missed_txs: Counter,
-
-//     height: Counter,
-//     resolve_block_duration: Histogram,
-//     block_gas_used: Histogram,
-//     block_gas_limit: Histogram,
-//     txs_in_block: Histogram,
-//     tx_gas_price: Histogram,
-//     process_block_duration: Histogram,
-// }
+struct Metrics {
+    heads: Counter,
+    height: Counter,
+    head_interval: Histogram,
+    resolve_block_duration: Histogram,
+    block_gas_used: Histogram,
+    block_gas_limit: Histogram,
+    txs_in_block: Histogram,
+    tx_priority_fee: Histogram,
+    process_block_duration: Histogram,
+    candidates: Counter,
+    sent_txs: Counter,
+    failed_txs: Counter,
+    reverted_txs: Counter,
+    fees_paid_gwei: Counter,
+    deadline_misses: Counter,
+}
 
-// impl Default for Metrics {
-//     fn default() -> Self {
-//         Self {
-//             seen_txs: register_counter!("sandwitch_seen_txs"),
-//             resolve_tx_duration: register_histogram!("sandwitch_resolve_tx_duration"),
-//             valid_txs: register_counter!("sandwitch_valid_txs"),
-//             resolved_as_pendning_txs: register_counter!("sandwitch_resolved_as_pending_txs"),
-//             process_tx_duration: register_histogram!("sandwitch_process_tx_duration"),
-//             missed_txs: register_counter!("sandwitch_missed_txs"),
-//             height: register_counter!("sandwitch_height"),
-//             resolve_block_duration: register_histogram!("sandwitch_resolve_block_duration"),
-//             block_gas_used: register_histogram!("sandwitch_block_gas_used"),
-//             block_gas_limit: register_histogram!("sandwitch_block_gas_limit"),
-//             txs_in_block: register_histogram!("sandwitch_txs_in_block"),
-//             tx_gas_price: register_histogram!("sandwitch_tx_gas_price"),
-//             process_block_duration: register_histogram!("sandwitch_process_block_duration"),
-//         }
-//     }
-// }
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            heads: register_counter!("sandwitch_heads"),
+            height: register_counter!("sandwitch_height"),
+            head_interval: register_histogram!("sandwitch_head_interval_duration"),
+            resolve_block_duration: register_histogram!("sandwitch_resolve_block_duration"),
+            block_gas_used: register_histogram!("sandwitch_block_gas_used"),
+            block_gas_limit: register_histogram!("sandwitch_block_gas_limit"),
+            txs_in_block: register_histogram!("sandwitch_txs_in_block"),
+            tx_priority_fee: register_histogram!("sandwitch_tx_priority_fee"),
+            process_block_duration: register_histogram!("sandwitch_process_block_duration"),
+            candidates: register_counter!("sandwitch_candidates"),
+            sent_txs: register_counter!("sandwitch_sent_txs"),
+            failed_txs: register_counter!("sandwitch_failed_txs"),
+            reverted_txs: register_counter!("sandwitch_reverted_txs"),
+            fees_paid_gwei: register_counter!("sandwitch_fees_paid_gwei"),
+            deadline_misses: register_counter!("sandwitch_deadline_misses"),
+        }
+    }
+}
 
-// impl Metrics {
-//     fn new_tx(&self) {
-//         self.seen_txs.increment(1);
-//     }
-
-//     fn tx_resolved(&self, elapsed: Duration) {
-//         self.resolve_tx_duration.record(elapsed)
-//     }
-
-//     fn tx_valid(&self) {
-//         self.valid_txs.increment(1);
-//     }
-
-//     fn tx_resolved_as_pending(&self) {
-//         self.resolved_as_pendning_txs.increment(1);
-//     }
-
-//     fn tx_processed(&self, elapsed: Duration) {
-//         self.process_tx_duration.record(elapsed);
-//     }
-
-//     fn tx_missed(&self) {
-//         self.missed_txs.increment(1);
-//     }
-
-//     fn new_block<TX>(&self, block: &Block<TX>) {
-//         self.height.absolute(block.number.unwrap().as_u64());
-//     }
-
-//     fn block_resolved(&self, elapsed: Duration) {
-//         self.resolve_block_duration.record(elapsed);
-//     }
-
-//     fn block_valid(&self, block: &Block<Transaction>) {
-//         self.block_gas_used.record(block.gas_used.as_u128() as f64);
-//         self.block_gas_limit
-//             .record(block.gas_limit.as_u128() as f64);
-//         self.txs_in_block.record(block.transactions.len() as f64);
-//         for gas_price in block.transactions.iter().filter_map(|tx| tx.gas_price) {
-//             self.tx_gas_price.record(gas_price.as_u128() as f64);
-//         }
-//     }
-
-//     fn block_processed(&self, elapsed: Duration) {
-//         self.process_block_duration.record(elapsed);
-//     }
-// }
+impl Metrics {
+    fn new_head<TX>(&self, block: &Block<TX>, since_last: Option<Duration>) {
+        self.heads.increment(1);
+        if let Some(number) = block.number {
+            self.height.absolute(number.as_u64());
+        }
+        if let Some(interval) = since_last {
+            self.head_interval.record(interval);
+        }
+    }
+
+    fn pending_block_resolved(&self, block: &Block<TxWithLogs>, elapsed: Duration) {
+        self.resolve_block_duration.record(elapsed);
+        self.block_gas_used.record(block.gas_used.as_u128() as f64);
+        self.block_gas_limit.record(block.gas_limit.as_u128() as f64);
+        self.txs_in_block.record(block.transactions.len() as f64);
+        for tx in &block.transactions {
+            self.tx_priority_fee
+                .record(tx.fees.priority_fee().as_u128() as f64);
+        }
+    }
+
+    fn included(&self, receipt: &TransactionReceipt) {
+        if receipt.status.is_some_and(|status| status.is_zero()) {
+            self.reverted_txs.increment(1);
+        }
+        let fee = receipt.gas_used.unwrap_or_default()
+            * receipt.effective_gas_price.unwrap_or_default();
+        self.fees_paid_gwei
+            .increment((fee / U256::exp10(9)).as_u64());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(tx_type: TxType, base_fee_per_gas: Option<U256>) -> TypedTransaction {
+        build_tx(
+            tx_type,
+            Address::repeat_byte(1),
+            Address::repeat_byte(2),
+            PrioritizedMultiCall {
+                calls: Default::default(),
+                priority_fee_per_gas: 3.into(),
+                monitors: Vec::new(),
+            },
+            base_fee_per_gas,
+        )
+    }
+
+    #[test]
+    fn build_tx_without_base_fee_is_legacy() {
+        let TypedTransaction::Legacy(tx) = build(TxType::Auto, None) else {
+            panic!("legacy transaction expected");
+        };
+        assert_eq!(tx.gas_price, Some(3.into()));
+        assert_eq!(tx.from, Some(Address::repeat_byte(1)));
+        assert_eq!(tx.to, Some(Address::repeat_byte(2).into()));
+    }
+
+    #[test]
+    fn build_tx_with_base_fee_is_eip1559() {
+        let TypedTransaction::Eip1559(tx) = build(TxType::Auto, Some(10.into())) else {
+            panic!("EIP-1559 transaction expected");
+        };
+        assert_eq!(tx.max_priority_fee_per_gas, Some(3.into()));
+        assert_eq!(tx.max_fee_per_gas, Some(13.into()));
+        assert_eq!(tx.from, Some(Address::repeat_byte(1)));
+        assert_eq!(tx.to, Some(Address::repeat_byte(2).into()));
+    }
+
+    #[test]
+    fn build_tx_follows_configured_type() {
+        let TypedTransaction::Legacy(tx) = build(TxType::Legacy, Some(10.into())) else {
+            panic!("legacy transaction expected");
+        };
+        assert_eq!(tx.gas_price, Some(13.into()));
+
+        let TypedTransaction::Eip1559(tx) = build(TxType::Eip1559, None) else {
+            panic!("EIP-1559 transaction expected");
+        };
+        assert_eq!(tx.max_priority_fee_per_gas, Some(3.into()));
+        assert_eq!(tx.max_fee_per_gas, Some(3.into()));
+    }
+
+    const MULTICALL: Address = Address::repeat_byte(2);
+    const BASE_TOKEN: Address = Address::repeat_byte(3);
+
+    fn transfer(from: Address, to: Address, amount: u64) -> ethers::types::Log {
+        ethers::types::Log {
+            address: BASE_TOKEN,
+            topics: vec![
+                H256::from(keccak256("Transfer(address,address,uint256)")),
+                from.into(),
+                to.into(),
+            ],
+            data: ethers::abi::encode(&[ethers::abi::Token::Uint(amount.into())]).into(),
+            ..Default::default()
+        }
+    }
+
+    fn receipt(status: u64, fee: u64, logs: Vec<ethers::types::Log>) -> TransactionReceipt {
+        TransactionReceipt {
+            status: Some(status.into()),
+            gas_used: Some(fee.into()),
+            effective_gas_price: Some(1.into()),
+            logs,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn outcome_of_front_and_back_run_is_accounted_together() {
+        let pair = Address::repeat_byte(4);
+        let front_run = receipt(1, 10, vec![transfer(MULTICALL, pair, 1_000)]);
+        let back_run = receipt(1, 10, vec![transfer(pair, MULTICALL, 1_030)]);
+        assert!(matches!(
+            outcome(MULTICALL, Some(BASE_TOKEN), &[&front_run, &back_run]),
+            TxOutcome::Succeeded
+        ));
+
+        // fees exceed the profit
+        let back_run = receipt(1, 10, vec![transfer(pair, MULTICALL, 1_015)]);
+        let TxOutcome::Unprofitable { loss } =
+            outcome(MULTICALL, Some(BASE_TOKEN), &[&front_run, &back_run])
+        else {
+            panic!("unprofitable outcome expected");
+        };
+        assert_eq!(loss, 5.into());
+    }
+
+    #[test]
+    fn outcome_of_reverted_tx_includes_its_fee() {
+        let TxOutcome::Reverted { loss } =
+            outcome(MULTICALL, Some(BASE_TOKEN), &[&receipt(0, 21, Vec::new())])
+        else {
+            panic!("reverted outcome expected");
+        };
+        assert_eq!(loss, 21.into());
+    }
+
+    #[test]
+    fn outcome_ignores_other_tokens() {
+        let mut log = transfer(Address::repeat_byte(4), MULTICALL, 1_000);
+        log.address = Address::repeat_byte(5);
+        let TxOutcome::Unprofitable { loss } =
+            outcome(MULTICALL, Some(BASE_TOKEN), &[&receipt(1, 10, vec![log])])
+        else {
+            panic!("unprofitable outcome expected");
+        };
+        assert_eq!(loss, 10.into());
+    }
+}
@@ -0,0 +1,465 @@
+use core::{fmt, iter};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use ethers::{
+    providers::{Middleware, MiddlewareError, ProviderError},
+    types::{
+        transaction::eip2718::TypedTransaction, AccessListItem, Address, Block, BlockId,
+        BlockNumber, H256, U256, U64,
+    },
+};
+use futures::{future::try_join_all, lock::Mutex as AsyncMutex, try_join};
+use itertools::Itertools;
+use revm::{
+    db::{CacheDB, DatabaseRef},
+    primitives::{
+        AccountInfo, BlockEnv, Bytecode, Bytes, CfgEnv, CreateScheme, EVMError, Env,
+        ExecutionResult, TransactTo, TxEnv, B160, B256, KECCAK_EMPTY, U256 as EvmU256,
+    },
+    EVM,
+};
+use sandwitch_contracts::ContractError;
+use tokio::runtime::Handle;
+use tracing::debug;
+
+use crate::{
+    block::TxWithLogs,
+    transactions::{Fees, Transaction},
+};
+
+/// State at a given block fetched from the node, fetched values are
+/// cached for all of the simulations
+struct NodeDb<M> {
+    client: Arc<M>,
+    block: BlockId,
+    handle: Handle,
+    /// Not locked while fetching, so simulations don't wait for each other
+    cache: Mutex<NodeCache>,
+}
+
+#[derive(Default)]
+struct NodeCache {
+    accounts: HashMap<B160, AccountInfo>,
+    contracts: HashMap<B256, Bytecode>,
+    storage: HashMap<(B160, EvmU256), EvmU256>,
+    block_hashes: HashMap<EvmU256, B256>,
+}
+
+impl NodeCache {
+    fn insert_account(&mut self, address: B160, info: AccountInfo) {
+        if let Some(code) = &info.code {
+            self.contracts.insert(info.code_hash, code.clone());
+        }
+        self.accounts.insert(address, info);
+    }
+}
+
+impl<M: Middleware> NodeDb<M> {
+    /// Fetches accounts and slots missing in cache concurrently, so they
+    /// are batched by the transport instead of being fetched one by one
+    /// while executing
+    async fn prefetch(&self, accesses: &[(B160, Vec<EvmU256>)]) -> Result<(), M::Error> {
+        let (accounts, slots) = {
+            let cache = self.cache.lock().unwrap();
+            let accounts = accesses
+                .iter()
+                .map(|(address, _)| *address)
+                .filter(|address| !cache.accounts.contains_key(address))
+                .unique()
+                .collect::<Vec<_>>();
+            let slots = accesses
+                .iter()
+                .flat_map(|(address, indexes)| indexes.iter().map(|index| (*address, *index)))
+                .filter(|slot| !cache.storage.contains_key(slot))
+                .unique()
+                .collect::<Vec<_>>();
+            (accounts, slots)
+        };
+        if accounts.is_empty() && slots.is_empty() {
+            return Ok(());
+        }
+        let (infos, values) = try_join!(
+            try_join_all(accounts.iter().map(|address| self.fetch_account(*address))),
+            try_join_all(
+                slots
+                    .iter()
+                    .map(|(address, index)| self.fetch_storage(*address, *index))
+            ),
+        )?;
+        debug!(
+            accounts = accounts.len(),
+            slots = slots.len(),
+            "prefetched state"
+        );
+        let mut cache = self.cache.lock().unwrap();
+        for (address, info) in accounts.into_iter().zip(infos) {
+            cache.insert_account(address, info);
+        }
+        cache.storage.extend(slots.into_iter().zip(values));
+        Ok(())
+    }
+
+    async fn fetch_account(&self, address: B160) -> Result<AccountInfo, M::Error> {
+        let address = Address::from(address.0);
+        let (balance, nonce, code) = try_join!(
+            self.client.get_balance(address, Some(self.block)),
+            self.client.get_transaction_count(address, Some(self.block)),
+            self.client.get_code(address, Some(self.block)),
+        )?;
+        Ok(AccountInfo::new(
+            to_evm(balance),
+            nonce.as_u64(),
+            Bytecode::new_raw(code.0),
+        ))
+    }
+
+    async fn fetch_storage(&self, address: B160, index: EvmU256) -> Result<EvmU256, M::Error> {
+        let value = self
+            .client
+            .get_storage_at(
+                Address::from(address.0),
+                H256(index.to_be_bytes::<32>()),
+                Some(self.block),
+            )
+            .await?;
+        Ok(EvmU256::from_be_bytes(value.0))
+    }
+}
+
+impl<M: Middleware> DatabaseRef for NodeDb<M> {
+    type Error = M::Error;
+
+    fn basic(&self, address: B160) -> Result<Option<AccountInfo>, Self::Error> {
+        if let Some(info) = self.cache.lock().unwrap().accounts.get(&address) {
+            return Ok(Some(info.clone()));
+        }
+        // not prefetched, so it is fetched while EVM waits for it
+        let info = self.handle.block_on(self.fetch_account(address))?;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert_account(address, info.clone());
+        Ok(Some(info))
+    }
+
+    fn code_by_hash(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        if code_hash == KECCAK_EMPTY {
+            return Ok(Bytecode::new());
+        }
+        // code is fetched along with the account, so it is known by now
+        self.cache
+            .lock()
+            .unwrap()
+            .contracts
+            .get(&code_hash)
+            .cloned()
+            .ok_or_else(|| {
+                M::Error::from_provider_err(ProviderError::CustomError(format!(
+                    "unknown code hash: {code_hash:?}"
+                )))
+            })
+    }
+
+    fn storage(&self, address: B160, index: EvmU256) -> Result<EvmU256, Self::Error> {
+        if let Some(value) = self.cache.lock().unwrap().storage.get(&(address, index)) {
+            return Ok(*value);
+        }
+        let value = self.handle.block_on(self.fetch_storage(address, index))?;
+        self.cache
+            .lock()
+            .unwrap()
+            .storage
+            .insert((address, index), value);
+        Ok(value)
+    }
+
+    fn block_hash(&self, number: EvmU256) -> Result<B256, Self::Error> {
+        if let Some(hash) = self.cache.lock().unwrap().block_hashes.get(&number) {
+            return Ok(*hash);
+        }
+        let block = self.handle.block_on(
+            self.client
+                .get_block(BlockNumber::Number(U64::from(number.as_limbs()[0]))),
+        )?;
+        let hash = block
+            .and_then(|block| block.hash)
+            .map(|hash| B256(hash.0))
+            .unwrap_or_default();
+        self.cache.lock().unwrap().block_hashes.insert(number, hash);
+        Ok(hash)
+    }
+}
+
+/// State at the end of the pending block, read-only for simulations
+struct PendingDb<M>(Arc<CacheDB<NodeDb<M>>>);
+
+impl<M: Middleware> DatabaseRef for PendingDb<M> {
+    type Error = M::Error;
+
+    fn basic(&self, address: B160) -> Result<Option<AccountInfo>, Self::Error> {
+        self.0.basic(address)
+    }
+
+    fn code_by_hash(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        self.0.code_by_hash(code_hash)
+    }
+
+    fn storage(&self, address: B160, index: EvmU256) -> Result<EvmU256, Self::Error> {
+        self.0.storage(address, index)
+    }
+
+    fn block_hash(&self, number: EvmU256) -> Result<B256, Self::Error> {
+        self.0.block_hash(number)
+    }
+}
+
+enum Pending<M> {
+    /// Applied by the first simulation
+    Unapplied(NodeDb<M>, Vec<TxEnv>),
+    Applied(Arc<CacheDB<NodeDb<M>>>),
+}
+
+/// Executes calls on top of a pending block in local EVM, fetching
+/// only the state it touches from the node at the parent block.
+/// Each call is executed in its own layer over the pending block,
+/// so that calls don't wait for each other.
+pub struct Simulator<M> {
+    env: Env,
+    pending: AsyncMutex<Option<Pending<M>>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Simulated {
+    pub gas_used: u64,
+    /// Output of the call or its revert data
+    pub output: Result<Bytes, Bytes>,
+}
+
+impl<M> Simulator<M> {
+    pub fn new(client: Arc<M>, chain_id: u64, block: &Block<TxWithLogs>) -> Self {
+        Self {
+            env: Env {
+                cfg: CfgEnv {
+                    chain_id: EvmU256::from(chain_id),
+                    ..Default::default()
+                },
+                block: BlockEnv {
+                    number: to_evm(block.number.unwrap_or_default().as_u64().into()),
+                    coinbase: B160(block.author.unwrap_or_default().0),
+                    timestamp: to_evm(block.timestamp),
+                    gas_limit: to_evm(block.gas_limit),
+                    basefee: to_evm(block.base_fee_per_gas.unwrap_or_default()),
+                    difficulty: to_evm(block.difficulty),
+                    prevrandao: Some(B256(block.mix_hash.unwrap_or_default().0)),
+                },
+                tx: Default::default(),
+            },
+            pending: AsyncMutex::new(Some(Pending::Unapplied(
+                NodeDb {
+                    client,
+                    block: BlockId::Hash(block.parent_hash),
+                    handle: Handle::current(),
+                    cache: Default::default(),
+                },
+                block
+                    .transactions
+                    .iter()
+                    .map(|tx| pending_tx_env(&tx.tx))
+                    .collect(),
+            ))),
+        }
+    }
+}
+
+impl<M> Simulator<M>
+where
+    M: Middleware + 'static,
+{
+    /// Simulates `tx` paying `gas_price` after all of the pending transactions
+    pub async fn simulate(
+        self: Arc<Self>,
+        tx: &TypedTransaction,
+        gas_price: U256,
+    ) -> Result<Simulated, ContractError<M>> {
+        let tx_env = TxEnv {
+            caller: B160(tx.from().copied().unwrap_or_default().0),
+            gas_limit: tx
+                .gas()
+                .map_or(self.env.block.gas_limit.to::<u64>(), U256::as_u64),
+            gas_price: to_evm(gas_price),
+            gas_priority_fee: None,
+            transact_to: tx
+                .to()
+                .and_then(|to| to.as_address())
+                .map_or(TransactTo::Create(CreateScheme::Create), |to| {
+                    TransactTo::Call(B160(to.0))
+                }),
+            value: to_evm(tx.value().copied().unwrap_or_default()),
+            data: tx.data().map(|data| data.0.clone()).unwrap_or_default(),
+            chain_id: None,
+            nonce: None,
+            access_list: Vec::new(),
+        };
+        let pending = self.pending_db().await;
+        // state touched by the pending block is already there, the rest
+        // is predicted by the node at the parent block
+        let mut accesses = accesses(&tx_env);
+        match pending
+            .db
+            .client
+            .create_access_list(tx, Some(pending.db.block))
+            .await
+        {
+            Ok(list) => accesses.extend(list.access_list.0.iter().map(access_list_item)),
+            Err(err) => debug!(?err, "failed to create access list"),
+        }
+        pending
+            .db
+            .prefetch(&accesses)
+            .await
+            .map_err(ContractError::MiddlewareError)?;
+        // EVM fetches state which was not prefetched synchronously
+        tokio::task::spawn_blocking(move || self.simulate_blocking(pending, tx_env))
+            .await
+            .expect("simulation panicked")
+    }
+
+    /// Applies pending transactions once, other simulations wait for it
+    async fn pending_db(&self) -> Arc<CacheDB<NodeDb<M>>> {
+        let mut pending = self.pending.lock().await;
+        let db = match pending.take().expect("simulator state is lost") {
+            Pending::Unapplied(db, txs) => {
+                let accesses = iter::once((self.env.block.coinbase, Vec::new()))
+                    .chain(txs.iter().flat_map(accesses))
+                    .collect::<Vec<_>>();
+                if let Err(err) = db.prefetch(&accesses).await {
+                    debug!(?err, "failed to prefetch state of pending transactions");
+                }
+                let env = self.env.clone();
+                Arc::new(
+                    tokio::task::spawn_blocking(move || apply_pending(env, db, txs))
+                        .await
+                        .expect("applying pending transactions panicked"),
+                )
+            }
+            Pending::Applied(db) => db,
+        };
+        *pending = Some(Pending::Applied(db.clone()));
+        db
+    }
+
+    fn simulate_blocking(
+        &self,
+        pending: Arc<CacheDB<NodeDb<M>>>,
+        tx: TxEnv,
+    ) -> Result<Simulated, ContractError<M>> {
+        let mut evm = EVM::new();
+        evm.env = self.env.clone();
+        evm.env.tx = tx;
+        // changes stay in the own layer of this call
+        evm.database(CacheDB::new(PendingDb(pending)));
+
+        Ok(match evm.transact().map_err(evm_error)?.result {
+            ExecutionResult::Success {
+                gas_used, output, ..
+            } => Simulated {
+                gas_used,
+                output: Ok(output.into_data()),
+            },
+            ExecutionResult::Revert { gas_used, output } => Simulated {
+                gas_used,
+                output: Err(output),
+            },
+            ExecutionResult::Halt { reason, .. } => {
+                return Err(ProviderError::CustomError(format!("halted: {reason:?}")).into())
+            }
+        })
+    }
+}
+
+impl<M> fmt::Debug for Simulator<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Simulator")
+            .field("block", &self.env.block.number)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Executes pending transactions on top of the parent block
+fn apply_pending<M: Middleware>(env: Env, db: NodeDb<M>, txs: Vec<TxEnv>) -> CacheDB<NodeDb<M>> {
+    let mut evm = EVM::new();
+    evm.env = env;
+    evm.database(CacheDB::new(db));
+    for tx in txs {
+        evm.env.tx = tx;
+        if let Err(err) = evm.transact_commit() {
+            debug!(?err, "failed to apply pending transaction");
+        }
+    }
+    evm.db.take().expect("database is set")
+}
+
+/// Accounts and slots known to be touched by `tx` upfront
+fn accesses(tx: &TxEnv) -> Vec<(B160, Vec<EvmU256>)> {
+    let mut accesses = vec![(tx.caller, Vec::new())];
+    if let TransactTo::Call(to) = tx.transact_to {
+        accesses.push((to, Vec::new()));
+    }
+    accesses.extend(tx.access_list.iter().cloned());
+    accesses
+}
+
+fn access_list_item(item: &AccessListItem) -> (B160, Vec<EvmU256>) {
+    (
+        B160(item.address.0),
+        item.storage_keys
+            .iter()
+            .map(|key| EvmU256::from_be_bytes(key.0))
+            .collect(),
+    )
+}
+
+fn pending_tx_env(tx: &Transaction) -> TxEnv {
+    let (gas_price, gas_priority_fee) = match tx.fees {
+        Fees::Legacy { gas_price } => (gas_price, None),
+        Fees::EIP1559 {
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+        } => (max_fee_per_gas, Some(to_evm(max_priority_fee_per_gas))),
+    };
+    TxEnv {
+        caller: B160(tx.from.0),
+        gas_limit: tx.gas.as_u64(),
+        gas_price: to_evm(gas_price),
+        gas_priority_fee,
+        transact_to: tx
+            .to
+            .map_or(TransactTo::Create(CreateScheme::Create), |to| {
+                TransactTo::Call(B160(to.0))
+            }),
+        value: to_evm(tx.value),
+        data: tx.input.0.clone(),
+        chain_id: tx.chain_id.map(|id| id.as_u64()),
+        nonce: Some(tx.nonce.as_u64()),
+        access_list: tx
+            .access_list
+            .iter()
+            .flat_map(|list| &list.0)
+            .map(access_list_item)
+            .collect(),
+    }
+}
+
+fn evm_error<M: Middleware>(err: EVMError<M::Error>) -> ContractError<M> {
+    match err {
+        EVMError::Database(err) => ContractError::MiddlewareError(err),
+        err => ProviderError::CustomError(format!("{err:?}")).into(),
+    }
+}
+
+fn to_evm(value: U256) -> EvmU256 {
+    EvmU256::from_limbs(value.0)
+}
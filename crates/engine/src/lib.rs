@@ -14,12 +14,20 @@ pub mod transactions;
 
 pub(crate) mod abort;
 pub mod block;
+pub mod breaker;
+pub mod control;
+pub mod deadline;
 // pub(crate) mod accounts;
-// pub(crate) mod cached;
+pub(crate) mod cached;
 mod engine;
 pub use engine::*;
+#[cfg(feature = "evm")]
+pub mod evm;
 // pub(crate) mod latency;
+pub mod limits;
+pub mod pending;
 pub mod providers;
+pub mod recorder;
 pub(crate) mod timed;
 
 pub mod monitor;
@@ -0,0 +1,196 @@
+use core::time::Duration;
+use std::{
+    borrow::Borrow,
+    collections::VecDeque,
+    sync::{Mutex, RwLock},
+};
+
+use ethers::types::{transaction::eip2718::TypedTransaction, U256};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use thiserror::Error as ThisError;
+use tokio::time::Instant;
+use tracing::error;
+
+use crate::config::{Ether, Gwei};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Hard caps on what we are allowed to spend, all of them are optional
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct SpendingLimitsConfig {
+    /// Max priority fee per gas (or gas price for legacy transactions), in gwei
+    #[serde_as(as = "Option<Gwei>")]
+    pub max_priority_fee_per_gas: Option<U256>,
+
+    /// Max fee per gas including base fee, in gwei
+    #[serde_as(as = "Option<Gwei>")]
+    pub max_fee_per_gas: Option<U256>,
+
+    /// Max gas cost of a single transaction, in ether
+    #[serde_as(as = "Option<Ether>")]
+    pub max_fee_per_tx: Option<U256>,
+
+    /// Max gas cost of all transactions sent in a single block, in ether
+    #[serde_as(as = "Option<Ether>")]
+    pub max_fees_per_block: Option<U256>,
+
+    /// Max gas cost of all transactions sent within rolling 24h window, in ether
+    #[serde_as(as = "Option<Ether>")]
+    pub max_fees_per_day: Option<U256>,
+
+    /// Max value sent with a single transaction, in ether
+    #[serde_as(as = "Option<Ether>")]
+    pub max_value: Option<U256>,
+}
+
+#[derive(ThisError, Debug)]
+pub enum LimitExceeded {
+    #[error("priority fee per gas {0} exceeds limit of {1}")]
+    PriorityFeePerGas(U256, U256),
+    #[error("fee per gas {0} exceeds limit of {1}")]
+    FeePerGas(U256, U256),
+    #[error("transaction fee {0} exceeds limit of {1}")]
+    FeePerTx(U256, U256),
+    #[error("fees per block {0} exceed limit of {1}")]
+    FeesPerBlock(U256, U256),
+    #[error("fees per 24h {0} exceed limit of {1}")]
+    FeesPerDay(U256, U256),
+    #[error("value {0} exceeds limit of {1}")]
+    Value(U256, U256),
+}
+
+pub struct SpendingLimits {
+    cfg: RwLock<SpendingLimitsConfig>,
+    /// Fees of sent transactions within last 24h
+    spent: Mutex<VecDeque<(Instant, U256)>>,
+}
+
+impl SpendingLimits {
+    pub fn new(cfg: SpendingLimitsConfig) -> Self {
+        Self {
+            cfg: RwLock::new(cfg),
+            spent: Default::default(),
+        }
+    }
+
+    pub fn config(&self) -> SpendingLimitsConfig {
+        self.cfg.read().unwrap().clone()
+    }
+
+    /// Replaces limits, fees spent so far are still accounted
+    pub fn set_config(&self, cfg: SpendingLimitsConfig) {
+        *self.cfg.write().unwrap() = cfg;
+    }
+
+    /// Drops transactions violating any of the limits, fees of the rest are
+    /// accounted once they are [charged](Self::charge). Transactions should be
+    /// ordered by priority, so that less important ones are the first to be dropped.
+    pub fn filter<T>(&self, txs: impl IntoIterator<Item = T>) -> Vec<T>
+    where
+        T: Borrow<TypedTransaction>,
+    {
+        let cfg = self.cfg.read().unwrap();
+        let mut spent_per_day = self.spent_per_day();
+        let mut spent_per_block = U256::zero();
+        let mut allowed = Vec::new();
+        for tx in txs {
+            match Self::check(&cfg, tx.borrow(), spent_per_block, spent_per_day) {
+                Ok(fee) => {
+                    spent_per_block += fee;
+                    spent_per_day += fee;
+                    allowed.push(tx);
+                }
+                Err(err) => error!(
+                    %err,
+                    to = ?tx.borrow().to_addr(),
+                    gas = ?tx.borrow().gas(),
+                    "spending limit exceeded, dropping transaction",
+                ),
+            }
+        }
+
+        allowed
+    }
+
+    /// Accounts max fee of a sent transaction as spent
+    pub fn charge(&self, fee: U256) {
+        self.spent.lock().unwrap().push_back((Instant::now(), fee));
+    }
+
+    fn spent_per_day(&self) -> U256 {
+        let now = Instant::now();
+        let mut spent = self.spent.lock().unwrap();
+        while spent
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) >= DAY)
+        {
+            spent.pop_front();
+        }
+        spent
+            .iter()
+            .fold(U256::zero(), |total, (_, fees)| total + fees)
+    }
+
+    /// Returns max fee of given transaction if it does not violate limits
+    fn check(
+        cfg: &SpendingLimitsConfig,
+        tx: &TypedTransaction,
+        spent_per_block: U256,
+        spent_per_day: U256,
+    ) -> Result<U256, LimitExceeded> {
+        fn ensure(
+            value: U256,
+            limit: Option<U256>,
+            err: fn(U256, U256) -> LimitExceeded,
+        ) -> Result<(), LimitExceeded> {
+            match limit {
+                Some(limit) if value > limit => Err(err(value, limit)),
+                _ => Ok(()),
+            }
+        }
+
+        let priority_fee_per_gas = match tx {
+            TypedTransaction::Eip1559(tx) => tx.max_priority_fee_per_gas,
+            _ => tx.gas_price(),
+        }
+        .unwrap_or_default();
+        let fee_per_gas = tx.gas_price().unwrap_or_default();
+        let fee = max_fee(tx);
+
+        ensure(
+            priority_fee_per_gas,
+            cfg.max_priority_fee_per_gas,
+            LimitExceeded::PriorityFeePerGas,
+        )?;
+        ensure(
+            fee_per_gas,
+            cfg.max_fee_per_gas,
+            LimitExceeded::FeePerGas,
+        )?;
+        ensure(fee, cfg.max_fee_per_tx, LimitExceeded::FeePerTx)?;
+        ensure(
+            spent_per_block + fee,
+            cfg.max_fees_per_block,
+            LimitExceeded::FeesPerBlock,
+        )?;
+        ensure(
+            spent_per_day + fee,
+            cfg.max_fees_per_day,
+            LimitExceeded::FeesPerDay,
+        )?;
+        ensure(
+            tx.value().copied().unwrap_or_default(),
+            cfg.max_value,
+            LimitExceeded::Value,
+        )?;
+        Ok(fee)
+    }
+}
+
+/// Max gas cost of the transaction
+pub fn max_fee(tx: &TypedTransaction) -> U256 {
+    tx.gas().copied().unwrap_or_default() * tx.gas_price().unwrap_or_default()
+}
@@ -1,4 +1,7 @@
-use std::{sync::Arc, vec};
+use std::{
+    sync::{Arc, RwLock},
+    vec,
+};
 
 use async_trait::async_trait;
 use ethers::{providers::Middleware, types::TxHash};
@@ -7,6 +10,8 @@ use futures::{
     TryFuture, TryFutureExt,
 };
 use impl_tools::autoimpl;
+use metrics::histogram;
+use tokio::time::Instant;
 
 use tracing::instrument;
 
@@ -59,6 +64,96 @@ impl<M: Middleware> BlockMonitor<M> for NoopMonitor {
     }
 }
 
+tokio::task_local! {
+    static MONITOR_NAME: &'static str;
+}
+
+/// Name of the [`Named`] monitor currently processing a block, if any
+pub(crate) fn current_monitor() -> Option<&'static str> {
+    MONITOR_NAME.try_with(|name| *name).ok()
+}
+
+/// Attributes everything inner monitor sends to given name, so that
+/// results of sent transactions can be tracked per monitor
+#[autoimpl(Deref using self.inner)]
+pub struct Named<M> {
+    name: &'static str,
+    inner: M,
+}
+
+impl<M> Named<M> {
+    pub fn new(name: &'static str, inner: M) -> Self {
+        Self { name, inner }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+#[async_trait]
+impl<MW, M> BlockMonitor<MW> for Named<M>
+where
+    MW: Middleware,
+    M: BlockMonitor<MW>,
+{
+    #[instrument(skip_all, fields(monitor.name = self.name))]
+    async fn process_block(&self, block: &ProcessingBlock<MW, TxHash>) -> anyhow::Result<()> {
+        MONITOR_NAME
+            .scope(self.name, self.inner.process_block(block))
+            .await
+    }
+
+    #[instrument(skip_all, fields(monitor.name = self.name))]
+    async fn process_pending_block(&self, block: &PendingBlock<MW>) -> anyhow::Result<()> {
+        let started_at = Instant::now();
+        let r = MONITOR_NAME
+            .scope(self.name, self.inner.process_pending_block(block))
+            .await;
+        histogram!("sandwitch_monitor_duration", started_at.elapsed(), "monitor" => self.name);
+        r
+    }
+}
+
+/// Monitor which can be replaced while the engine is running,
+/// each block is processed by the one which was set when it came
+pub struct Swappable<M>(Arc<RwLock<Arc<M>>>);
+
+impl<M> Swappable<M> {
+    pub fn new(inner: M) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(inner))))
+    }
+
+    pub fn swap(&self, inner: M) {
+        *self.0.write().unwrap() = Arc::new(inner);
+    }
+
+    fn current(&self) -> Arc<M> {
+        self.0.read().unwrap().clone()
+    }
+}
+
+impl<M> Clone for Swappable<M> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+#[async_trait]
+impl<MW, M> BlockMonitor<MW> for Swappable<M>
+where
+    MW: Middleware,
+    M: BlockMonitor<MW>,
+{
+    async fn process_block(&self, block: &ProcessingBlock<MW, TxHash>) -> anyhow::Result<()> {
+        self.current().process_block(block).await
+    }
+
+    async fn process_pending_block(&self, block: &PendingBlock<MW>) -> anyhow::Result<()> {
+        self.current().process_pending_block(block).await
+    }
+}
+
 #[autoimpl(Deref using self.0)]
 #[autoimpl(DerefMut using self.0)]
 pub struct MultiMonitor<M>(Vec<M>);
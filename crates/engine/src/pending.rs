@@ -0,0 +1,114 @@
+use core::{cmp::Reverse, mem};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use ethers::types::{Address, Block, Transaction, TxHash, U256};
+use impl_tools::autoimpl;
+use metrics::{register_counter, Counter};
+
+use crate::transactions::Fees;
+
+/// View of the mempool other than the one of engine's client,
+/// e.g. a node among several ones
+#[async_trait]
+#[autoimpl(for<T: trait + ?Sized> &T, Box<T>, Arc<T>)]
+pub trait PendingView: Send + Sync {
+    /// Name of the view in logs and metrics
+    fn name(&self) -> &str;
+
+    async fn pending_block(&self) -> anyhow::Result<Option<Block<Transaction>>>;
+}
+
+/// Merges pending blocks of several views given in order they were
+/// received. The first received of the latest blocks is taken as a base
+/// with transactions from the other ones of the same number added, ordered by effective
+/// priority fee while keeping nonce order of each sender. Of transactions
+/// with the same sender and nonce, only the one paying more is kept, since
+/// the other one is replaced by it.
+/// Returns name of the view each transaction was first seen by.
+pub(crate) fn merge(
+    mut blocks: Vec<(Arc<str>, Block<Transaction>)>,
+    metrics: &mut ViewMetrics,
+) -> Option<(Block<Transaction>, HashMap<TxHash, Arc<str>>)> {
+    let latest = blocks.iter().map(|(_, block)| block.number).max()?;
+    blocks.retain(|(_, block)| block.number == latest);
+    let base_fee = blocks.first()?.1.base_fee_per_gas.unwrap_or_default();
+    let fee = |tx: &Transaction| {
+        Fees::try_from(tx).map_or(U256::zero(), |fees| fees.effective_priority_fee(base_fee))
+    };
+
+    let mut seen_by = HashMap::new();
+    let mut transactions: Vec<Transaction> = Vec::new();
+    let mut by_nonce: HashMap<(Address, U256), usize> = HashMap::new();
+    let mut merged = None;
+    for (name, mut block) in blocks {
+        let counter = metrics.first_seen(&name);
+        for tx in block.transactions.drain(..) {
+            let Entry::Vacant(entry) = seen_by.entry(tx.hash) else {
+                continue;
+            };
+            let replaced = match by_nonce.entry((tx.from, tx.nonce)) {
+                Entry::Vacant(nonce) => {
+                    nonce.insert(transactions.len());
+                    transactions.push(tx);
+                    None
+                }
+                Entry::Occupied(nonce) => {
+                    let replaced = &mut transactions[*nonce.get()];
+                    if fee(&tx) <= fee(replaced) {
+                        continue;
+                    }
+                    Some(mem::replace(replaced, tx).hash)
+                }
+            };
+            entry.insert(name.clone());
+            counter.increment(1);
+            if let Some(replaced) = replaced {
+                seen_by.remove(&replaced);
+            }
+        }
+        merged.get_or_insert(block);
+    }
+    let mut merged = merged?;
+
+    // stable, so that order of the same fee is kept
+    transactions.sort_by_key(|tx| Reverse(fee(tx)));
+    keep_nonce_order(&mut transactions);
+    merged.transactions = transactions;
+    Some((merged, seen_by))
+}
+
+/// Reorders transactions of each sender by nonce keeping the
+/// positions they occupy
+fn keep_nonce_order(transactions: &mut [Transaction]) {
+    let mut by_sender: HashMap<Address, Vec<usize>> = HashMap::new();
+    for (i, tx) in transactions.iter().enumerate() {
+        by_sender.entry(tx.from).or_default().push(i);
+    }
+    for positions in by_sender.into_values().filter(|p| p.len() > 1) {
+        let mut txs: Vec<_> = positions.iter().map(|&i| transactions[i].clone()).collect();
+        txs.sort_by_key(|tx| tx.nonce);
+        for (i, tx) in positions.into_iter().zip(txs) {
+            transactions[i] = tx;
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct ViewMetrics {
+    first_seen: HashMap<Arc<str>, Counter>,
+}
+
+impl ViewMetrics {
+    fn first_seen(&mut self, view: &Arc<str>) -> Counter {
+        self.first_seen
+            .entry(view.clone())
+            .or_insert_with(|| {
+                register_counter!("sandwitch_pending_txs_first_seen", "view" => view.to_string())
+            })
+            .clone()
+    }
+}
@@ -0,0 +1,285 @@
+use core::{fmt::Debug, future::Future, marker, pin::Pin};
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use ethers::{
+    providers::{JsonRpcClient, JsonRpcError, ProviderError, PubsubClient, RpcError},
+    types::{U256, U64},
+};
+use futures::{
+    stream::{BoxStream, StreamExt},
+    FutureExt,
+};
+use metrics::register_counter;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, value::RawValue, Value};
+use thiserror::Error as ThisError;
+
+use crate::cached::CachedAt;
+
+/// Selectors of `name()`, `symbol()` and `decimals()` of ERC20 tokens,
+/// which never change
+const TOKEN_METADATA: &[&str] = &["0x06fdde03", "0x95d89b41", "0x313ce567"];
+
+/// Caches responses to deterministic requests, so that monitors can
+/// re-query the same state without hitting the node. Responses are
+/// keyed by the head they were received at and dropped on the next one,
+/// except for the ones which never change, e.g. token metadata.
+pub struct CacheProvider<P> {
+    inner: P,
+    cache: Arc<Cache>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    /// Number of the head response is valid for, [`None`] if forever
+    head: Option<u64>,
+    method: String,
+    params: String,
+}
+
+struct Cache {
+    responses: CachedAt<CacheKey, Box<RawValue>>,
+    head: AtomicU64,
+    /// Head responses of previous ones were dropped at
+    pruned_at: AtomicU64,
+    /// Subscriptions to new heads, which are watched to drop responses
+    head_subscriptions: Mutex<HashSet<U256>>,
+}
+
+impl Cache {
+    fn on_head(&self, notification: &RawValue) {
+        #[derive(Deserialize)]
+        struct Head {
+            number: Option<U64>,
+        }
+
+        if let Ok(Head {
+            number: Some(number),
+        }) = serde_json::from_str(notification.get())
+        {
+            self.head.fetch_max(number.as_u64(), Ordering::Relaxed);
+        }
+    }
+
+    /// Drops responses received at previous heads
+    async fn prune(&self) -> u64 {
+        let head = self.head.load(Ordering::Relaxed);
+        if self.pruned_at.fetch_max(head, Ordering::Relaxed) < head {
+            self.responses
+                .retain(|key| key.head.map_or(true, |at| at >= head))
+                .await;
+        }
+        head
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Scope {
+    /// Response never changes
+    Forever,
+    /// Response does not change until the next head
+    Head,
+}
+
+impl Scope {
+    fn of(method: &str, params: &Value) -> Option<Self> {
+        match method {
+            "eth_chainId" | "net_version" => Some(Self::Forever),
+            "eth_call" => {
+                let call = params.get(0)?;
+                let data = call.get("data").or_else(|| call.get("input"))?.as_str()?;
+                if TOKEN_METADATA.contains(&data) {
+                    return Some(Self::Forever);
+                }
+                // state overrides are not worth caching
+                (params.get(2).is_none() && is_pinned(params.get(1))).then_some(Self::Head)
+            }
+            "eth_getCode" => {
+                (params.get(1).and_then(Value::as_str) != Some("pending")).then_some(Self::Head)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Whether the block is given by its hash or number
+fn is_pinned(block: Option<&Value>) -> bool {
+    match block {
+        Some(Value::String(id)) => id.starts_with("0x"),
+        Some(Value::Object(id)) => id.contains_key("blockHash") || id.contains_key("blockNumber"),
+        _ => false,
+    }
+}
+
+impl<P> CacheProvider<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            cache: Arc::new(Cache {
+                responses: Default::default(),
+                head: AtomicU64::new(0),
+                pruned_at: AtomicU64::new(0),
+                head_subscriptions: Default::default(),
+            }),
+        }
+    }
+}
+
+impl<P: Debug> Debug for CacheProvider<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CacheProvider")
+            .field("inner", &self.inner)
+            .field("head", &self.cache.head)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P> JsonRpcClient for CacheProvider<P>
+where
+    P: JsonRpcClient,
+    P::Error: 'static,
+{
+    type Error = CacheProviderError<P::Error>;
+
+    fn request<'life0, 'life1, 'async_trait, T, R>(
+        &'life0 self,
+        method: &'life1 str,
+        params: T,
+    ) -> Pin<Box<dyn Future<Output = Result<R, Self::Error>> + marker::Send + 'async_trait>>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+        T: 'async_trait,
+        R: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+    {
+        async move {
+            let params = serde_json::to_value(&params)?;
+            if method == "eth_subscribe" && params.get(0) == Some(&json!("newHeads")) {
+                let id: U256 = self
+                    .inner
+                    .request(method, &params)
+                    .await
+                    .map_err(CacheProviderError::Inner)?;
+                self.cache.head_subscriptions.lock().unwrap().insert(id);
+                return Ok(serde_json::from_value(json!(id))?);
+            }
+            let Some(scope) = Scope::of(method, &params) else {
+                return self
+                    .inner
+                    .request(method, params)
+                    .await
+                    .map_err(CacheProviderError::Inner);
+            };
+
+            let head = self.cache.prune().await;
+            let key = CacheKey {
+                head: match scope {
+                    Scope::Forever => None,
+                    Scope::Head => Some(head),
+                },
+                method: method.to_string(),
+                params: params.to_string(),
+            };
+            let mut missed = false;
+            let response = self
+                .cache
+                .responses
+                .get_at_or_try_insert_with(key, |_| {
+                    missed = true;
+                    self.inner.request::<_, Box<RawValue>>(method, &params)
+                })
+                .await
+                .map_err(CacheProviderError::Inner)?;
+            if missed {
+                register_counter!("sandwitch_rpc_cache_misses", "method" => method.to_string())
+                    .increment(1);
+            } else {
+                register_counter!("sandwitch_rpc_cache_hits", "method" => method.to_string())
+                    .increment(1);
+            }
+            Ok(serde_json::from_str(response.get())?)
+        }
+        .boxed()
+    }
+}
+
+impl<P> PubsubClient for CacheProvider<P>
+where
+    P: PubsubClient,
+    P::Error: 'static,
+    P::NotificationStream: 'static,
+{
+    type NotificationStream = BoxStream<'static, Box<RawValue>>;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        let id = id.into();
+        let notifications = self
+            .inner
+            .subscribe(id)
+            .map_err(CacheProviderError::Inner)?;
+        if !self.cache.head_subscriptions.lock().unwrap().contains(&id) {
+            return Ok(notifications.boxed());
+        }
+        let cache = self.cache.clone();
+        Ok(notifications
+            .inspect(move |head| cache.on_head(head))
+            .boxed())
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        let id = id.into();
+        self.cache.head_subscriptions.lock().unwrap().remove(&id);
+        self.inner
+            .unsubscribe(id)
+            .map_err(CacheProviderError::Inner)
+    }
+}
+
+#[derive(ThisError, Debug)]
+pub enum CacheProviderError<P> {
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Inner(P),
+}
+
+impl<P> RpcError for CacheProviderError<P>
+where
+    P: Into<ProviderError> + RpcError,
+{
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Inner(inner) => inner.as_error_response(),
+            Self::Serde(_) => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Inner(inner) => inner.as_serde_error(),
+            Self::Serde(err) => Some(err),
+        }
+    }
+}
+
+impl<P> From<CacheProviderError<P>> for ProviderError
+where
+    P: Into<ProviderError> + RpcError + 'static,
+{
+    fn from(e: CacheProviderError<P>) -> Self {
+        match e {
+            CacheProviderError::Inner(e) => e.into(),
+            CacheProviderError::Serde(e) => e.into(),
+        }
+    }
+}
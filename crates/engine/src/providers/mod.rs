@@ -1,2 +1,4 @@
+mod cache;
+pub use cache::*;
 mod latency;
 pub use latency::*;
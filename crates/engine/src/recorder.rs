@@ -0,0 +1,151 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use ethers::types::{transaction::eip2718::TypedTransaction, Block, Bytes, TxHash};
+use flate2::{write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::block::{PrioritizedMultiCall, TxWithLogs};
+
+const SEGMENT_EXTENSION: &str = "jsonl.gz";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RecorderConfig {
+    /// Directory to write segments to
+    pub dir: PathBuf,
+
+    /// Delete oldest segments when there are more than this many
+    pub max_segments: Option<usize>,
+
+    /// Gzip compression level from 0 to 9
+    #[serde(default = "RecorderConfig::default_compression")]
+    pub compression: u32,
+}
+
+impl RecorderConfig {
+    fn default_compression() -> u32 {
+        Compression::default().level()
+    }
+}
+
+/// Entry of a segment, each segment is a gzipped JSONL file with all
+/// entries recorded while processing a single pending block
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record<B = Block<TxWithLogs>, C = Vec<PrioritizedMultiCall>> {
+    /// Pending block with logs of its transactions
+    PendingBlock { block: B },
+    /// Calls produced by monitors before being reduced
+    Produced { calls: C },
+    /// Transactions left after reducing calls and applying spending limits
+    Reduced { txs: Vec<ReducedTx> },
+    /// Signed transaction along with the result of sending it
+    Sent {
+        raw: Bytes,
+        result: Result<TxHash, String>,
+    },
+}
+
+/// Borrowing [`Record`] for recording without cloning
+pub(crate) type RecordRef<'a> = Record<&'a Block<TxWithLogs>, &'a [PrioritizedMultiCall]>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReducedTx {
+    pub tx: TypedTransaction,
+    pub monitors: Vec<String>,
+}
+
+/// Writes each processed block into its own segment in configured directory
+pub struct Recorder {
+    cfg: RecorderConfig,
+    segments: Mutex<VecDeque<PathBuf>>,
+}
+
+impl Recorder {
+    pub fn new(cfg: RecorderConfig) -> io::Result<Self> {
+        fs::create_dir_all(&cfg.dir)?;
+        let mut segments: Vec<_> = fs::read_dir(&cfg.dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .filter(|path| {
+                path.as_ref().map_or(true, |path| {
+                    path.to_str()
+                        .is_some_and(|path| path.ends_with(SEGMENT_EXTENSION))
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        // names are zero-padded block numbers, so they are sorted chronologically
+        segments.sort();
+        Ok(Self {
+            cfg,
+            segments: Mutex::new(segments.into()),
+        })
+    }
+
+    /// Creates segment for given block, rotating old ones
+    pub fn segment(&self, block_number: u64) -> Option<Arc<Segment>> {
+        let path = self
+            .cfg
+            .dir
+            .join(format!("{block_number:012}.{SEGMENT_EXTENSION}"));
+        let segment = match File::create(&path) {
+            Ok(file) => Segment {
+                out: Mutex::new(GzEncoder::new(
+                    BufWriter::new(file),
+                    Compression::new(self.cfg.compression),
+                )),
+            },
+            Err(err) => {
+                error!(%err, path = %path.display(), "failed to create recording segment");
+                return None;
+            }
+        };
+
+        let mut segments = self.segments.lock().unwrap();
+        segments.push_back(path);
+        while self
+            .cfg
+            .max_segments
+            .is_some_and(|max| segments.len() > max)
+        {
+            let Some(oldest) = segments.pop_front() else {
+                break;
+            };
+            if let Err(err) = fs::remove_file(&oldest) {
+                warn!(%err, path = %oldest.display(), "failed to remove old recording segment");
+            }
+        }
+        Some(Arc::new(segment))
+    }
+}
+
+/// Segment is finished when the last reference to it is dropped
+pub struct Segment {
+    out: Mutex<GzEncoder<BufWriter<File>>>,
+}
+
+impl Segment {
+    pub(crate) fn record(&self, record: &RecordRef<'_>) {
+        let mut out = self.out.lock().unwrap();
+        if let Err(err) = serde_json::to_writer(&mut *out, record)
+            .map_err(io::Error::from)
+            .and_then(|()| out.write_all(b"\n"))
+        {
+            error!(%err, "failed to record");
+        }
+    }
+}
+
+impl Drop for Segment {
+    fn drop(&mut self) {
+        let out = self.out.get_mut().unwrap();
+        if let Err(err) = out.try_finish().and_then(|()| out.get_mut().flush()) {
+            error!(%err, "failed to finish recording segment");
+        }
+    }
+}
@@ -1,14 +1,11 @@
-use ethers::types::{transaction::eip2930::AccessList, Address, Bytes, OtherFields, H256, U256};
+use ethers::types::{
+    transaction::eip2930::AccessList, Address, Bytes, OtherFields, H256, U256, U64,
+};
+use serde::{Deserialize, Serialize};
 use thiserror::Error as ThisError;
 
-#[cfg(not(feature = "legacy"))]
-pub type TransactionRequest = ethers::types::transaction::eip1559::Eip1559TransactionRequest;
-
-#[cfg(feature = "legacy")]
-pub type TransactionRequest = ethers::types::TransactionRequest;
-
 /// Clone implentation of [`ethers::types::Transaction`]
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Transaction {
     /// The transaction's hash
     pub hash: H256,
@@ -57,14 +54,17 @@ pub struct Transaction {
     pub chain_id: Option<U256>,
 
     /// Captures unknown fields such as additional fields used by L2s
+    #[serde(flatten)]
     pub other: OtherFields,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Fees {
     Legacy {
         gas_price: U256,
     },
+    #[serde(rename = "eip1559")]
     EIP1559 {
         /// Represents the maximum tx fee that will go to the miner as part of the user's
         /// fee payment. It serves 3 purposes:
@@ -85,6 +85,27 @@ pub enum Fees {
 }
 
 impl Fees {
+    fn new(
+        transaction_type: Option<U64>,
+        gas_price: Option<U256>,
+        max_priority_fee_per_gas: Option<U256>,
+        max_fee_per_gas: Option<U256>,
+    ) -> Result<Self, InvalidTransaction> {
+        Ok(if transaction_type.is_some_and(|n| n == 2.into()) {
+            let (max_priority_fee_per_gas, max_fee_per_gas) = max_priority_fee_per_gas
+                .zip(max_fee_per_gas)
+                .ok_or(InvalidTransaction::NoEIP1559Fees)?;
+            Self::EIP1559 {
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+            }
+        } else {
+            Self::Legacy {
+                gas_price: gas_price.ok_or(InvalidTransaction::NoLegacyGasPrice)?,
+            }
+        })
+    }
+
     pub fn priority_fee(&self) -> U256 {
         match self {
             Fees::Legacy { gas_price } => *gas_price,
@@ -94,6 +115,20 @@ impl Fees {
             } => *max_priority_fee_per_gas,
         }
     }
+
+    /// Fee per gas paid on top of the base fee, which is what is left
+    /// of gas price for legacy transactions
+    pub fn effective_priority_fee(&self, base_fee_per_gas: U256) -> U256 {
+        match self {
+            Fees::Legacy { gas_price } => gas_price.saturating_sub(base_fee_per_gas),
+            Fees::EIP1559 {
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+            } => max_fee_per_gas
+                .saturating_sub(base_fee_per_gas)
+                .min(*max_priority_fee_per_gas),
+        }
+    }
 }
 
 #[derive(ThisError, Debug)]
@@ -105,6 +140,19 @@ pub enum InvalidTransaction {
     NoLegacyGasPrice,
 }
 
+impl TryFrom<&ethers::types::Transaction> for Fees {
+    type Error = InvalidTransaction;
+
+    fn try_from(tx: &ethers::types::Transaction) -> Result<Self, Self::Error> {
+        Self::new(
+            tx.transaction_type,
+            tx.gas_price,
+            tx.max_priority_fee_per_gas,
+            tx.max_fee_per_gas,
+        )
+    }
+}
+
 impl TryFrom<ethers::types::Transaction> for Transaction {
     type Error = InvalidTransaction;
 
@@ -142,19 +190,12 @@ impl TryFrom<ethers::types::Transaction> for Transaction {
             value,
             gas,
             input,
-            fees: if transaction_type.is_some_and(|n| n == 2.into()) {
-                let (max_priority_fee_per_gas, max_fee_per_gas) = max_priority_fee_per_gas
-                    .zip(max_fee_per_gas)
-                    .ok_or(InvalidTransaction::NoEIP1559Fees)?;
-                Fees::EIP1559 {
-                    max_priority_fee_per_gas,
-                    max_fee_per_gas,
-                }
-            } else {
-                Fees::Legacy {
-                    gas_price: gas_price.ok_or(InvalidTransaction::NoLegacyGasPrice)?,
-                }
-            },
+            fees: Fees::new(
+                transaction_type,
+                gas_price,
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+            )?,
             v: v.as_u64(),
             r,
             s,
@@ -223,3 +264,30 @@ impl From<Transaction> for ethers::types::Transaction {
         tx
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_effective_priority_fee() {
+        let fees = Fees::Legacy {
+            gas_price: 15.into(),
+        };
+        assert_eq!(fees.effective_priority_fee(0.into()), 15.into());
+        assert_eq!(fees.effective_priority_fee(10.into()), 5.into());
+        assert_eq!(fees.effective_priority_fee(20.into()), 0.into());
+    }
+
+    #[test]
+    fn eip1559_effective_priority_fee() {
+        let fees = Fees::EIP1559 {
+            max_priority_fee_per_gas: 3.into(),
+            max_fee_per_gas: 15.into(),
+        };
+        assert_eq!(fees.effective_priority_fee(0.into()), 3.into());
+        // capped by max fee
+        assert_eq!(fees.effective_priority_fee(13.into()), 2.into());
+        assert_eq!(fees.effective_priority_fee(20.into()), 0.into());
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use ethers::{
+    contract::ContractError,
+    providers::Middleware,
+    types::{Address, BlockId, BlockNumber, U256},
+};
+use sandwitch_contracts::{
+    erc20::ERC20,
+    pancake_swap::{factory::PancakeFactory, pair::PancakePair},
+};
+use sandwitch_engine::{block::PendingBlock, config::Ether};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+/// Caps on how much of the base token we put into a single front run.
+/// All amounts are in the base token, given in ether units (18 decimals).
+#[serde_as]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExposureLimits {
+    /// Absolute cap on the amount in
+    #[serde_as(as = "Option<Ether>")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_amount_in: Option<U256>,
+
+    /// Cap as a fraction of base token inventory held by the multicall
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inventory_fraction: Option<f64>,
+
+    /// Cap as a fraction of base token reserve of the pair
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub liquidity_fraction: Option<f64>,
+}
+
+impl ExposureLimits {
+    /// Fills limits not set here from `fallback`
+    fn or(self, fallback: Self) -> Self {
+        Self {
+            max_amount_in: self.max_amount_in.or(fallback.max_amount_in),
+            inventory_fraction: self.inventory_fraction.or(fallback.inventory_fraction),
+            liquidity_fraction: self.liquidity_fraction.or(fallback.liquidity_fraction),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairExposureLimits {
+    pub tokens: [Address; 2],
+
+    #[serde(flatten)]
+    pub limits: ExposureLimits,
+}
+
+/// Exposure policy, the most specific limits win: per pair, then
+/// per token bought with the base token, then defaults
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExposureConfig {
+    #[serde(flatten)]
+    pub default: ExposureLimits,
+
+    pub tokens: HashMap<Address, ExposureLimits>,
+
+    pub pairs: Vec<PairExposureLimits>,
+}
+
+impl ExposureConfig {
+    pub fn limits(&self, token_in: Address, token_out: Address) -> ExposureLimits {
+        let pair = self
+            .pairs
+            .iter()
+            .find(|p| p.tokens == [token_in, token_out] || p.tokens == [token_out, token_in])
+            .map(|p| p.limits)
+            .unwrap_or_default();
+        let token = self.tokens.get(&token_out).copied().unwrap_or_default();
+        pair.or(token).or(self.default)
+    }
+
+    /// Max amount of `token_in` our front run of `token_in -> token_out`
+    /// swap is allowed to spend, queries the chain only for configured
+    /// fractional limits
+    pub async fn max_amount_in<M: Middleware>(
+        &self,
+        block: &PendingBlock<M>,
+        factory: Address,
+        token_in: Address,
+        token_out: Address,
+    ) -> Result<U256, ContractError<M>> {
+        let limits = self.limits(token_in, token_out);
+        let client = block.client();
+        let pending = BlockId::Number(BlockNumber::Pending);
+        let mut max = limits.max_amount_in.unwrap_or(U256::MAX);
+
+        if let Some(fraction) = limits.inventory_fraction {
+            let inventory = ERC20::new(token_in, client.clone())
+                .balance_of(block.multicall())
+                .block(pending)
+                .call()
+                .await?;
+            max = max.min(fraction_of(inventory, fraction));
+        }
+
+        if let Some(fraction) = limits.liquidity_fraction {
+            let pair = PancakeFactory::new(factory, client.clone())
+                .get_pair(token_in, token_out)
+                .block(pending)
+                .call()
+                .await?;
+            let pair = PancakePair::new(pair, client);
+            let (token_0, (reserve_0, reserve_1, _)) = futures::try_join!(
+                pair.token_0().block(pending).call(),
+                pair.get_reserves().block(pending).call(),
+            )?;
+            let reserve_in = if token_0 == token_in {
+                reserve_0
+            } else {
+                reserve_1
+            };
+            max = max.min(fraction_of(reserve_in.into(), fraction));
+        }
+
+        Ok(max)
+    }
+}
+
+fn fraction_of(amount: U256, fraction: f64) -> U256 {
+    const PRECISION: u64 = 1_000_000;
+    let fraction = (fraction.clamp(0.0, 1.0) * PRECISION as f64) as u64;
+    amount / PRECISION * fraction + amount % PRECISION * fraction / PRECISION
+}
@@ -29,10 +29,11 @@ use sandwitch_engine::{
     transactions::Transaction,
 };
 use serde::{Deserialize, Serialize};
-use tracing::{debug, info, instrument};
+use tracing::{debug, info, instrument, warn};
 
-use self::swap::Swap;
+use self::{exposure::ExposureConfig, swap::Swap};
 
+pub mod exposure;
 // mod factory;
 // mod pair;
 mod swap;
@@ -42,6 +43,9 @@ pub struct PancakeConfig {
     pub router: Address,
     pub toaster: Address,
     pub base_token: Address,
+
+    #[serde(default)]
+    pub exposure: ExposureConfig,
 }
 
 pub struct PancakeMonitor {
@@ -49,6 +53,7 @@ pub struct PancakeMonitor {
     factory: Address,
     toaster: Address,
     base_token: Address,
+    exposure: ExposureConfig,
 }
 
 impl PancakeMonitor {
@@ -69,6 +74,7 @@ impl PancakeMonitor {
             factory,
             toaster: cfg.toaster,
             base_token: cfg.base_token,
+            exposure: cfg.exposure,
         })
     }
 }
@@ -76,7 +82,7 @@ impl PancakeMonitor {
 #[async_trait]
 impl<M> BlockMonitor<M> for PancakeMonitor
 where
-    M: Middleware,
+    M: Middleware + 'static,
 {
     async fn process_pending_block(&self, block: &PendingBlock<M>) -> anyhow::Result<()> {
         for adjacent_txs in block.iter_adjacent_txs() {
@@ -85,35 +91,47 @@ where
                 swaps.on_tx(tx);
             }
 
-            let (front_run_calls, back_run_calls): (Calls<_>, Calls<_>) = swaps
-                .into_independent()
-                .filter_map(|s| {
-                    let index_in = s.path.iter().position(|token| *token == self.base_token)?;
-                    if index_in == s.path.len() - 1 {
-                        return None;
-                    }
-                    let back_run = sandwitch_contracts::pancake_toaster::BackRunSwapAllCall {
-                        token_in: s.path[index_in],
-                        token_out: s.path[index_in + 1],
-                    };
-                    Some((
-                        ContractCall::new(
-                            self.toaster,
-                            sandwitch_contracts::pancake_toaster::FrontRunSwapCall {
-                                from: s.from,
-                                amount_in: s.amount_in,
-                                amount_out: s.amount_out,
-                                eth_in: s.eth_in,
-                                path: s.path,
-                                index_in: index_in.into(),
-                            },
-                        )
-                        .maybe()
-                        .into_dyn(),
-                        ContractCall::new(self.toaster, back_run).maybe().into_dyn(),
-                    ))
-                })
-                .unzip();
+            let (mut front_run_calls, mut back_run_calls): (Calls<_>, Calls<_>) =
+                Default::default();
+            for s in swaps.into_independent() {
+                let Some(index_in) = s.path.iter().position(|token| *token == self.base_token) else {
+                    continue;
+                };
+                if index_in == s.path.len() - 1 {
+                    continue;
+                }
+                let (token_in, token_out) = (s.path[index_in], s.path[index_in + 1]);
+                let max_amount_in = self
+                    .exposure
+                    .max_amount_in(block, self.factory, token_in, token_out)
+                    .await?;
+                if max_amount_in.is_zero() {
+                    warn!(?token_in, ?token_out, "exposure limit reached, skipping swap");
+                    continue;
+                }
+
+                let back_run = sandwitch_contracts::pancake_toaster::BackRunSwapAllCall {
+                    token_in,
+                    token_out,
+                };
+                front_run_calls.push(
+                    ContractCall::new(
+                        self.toaster,
+                        sandwitch_contracts::pancake_toaster::FrontRunSwapCall {
+                            from: s.from,
+                            amount_in: s.amount_in,
+                            amount_out: s.amount_out,
+                            eth_in: s.eth_in,
+                            path: s.path,
+                            index_in: index_in.into(),
+                            max_amount_in,
+                        },
+                    )
+                    .maybe()
+                    .into_dyn(),
+                );
+                back_run_calls.push(ContractCall::new(self.toaster, back_run).maybe().into_dyn());
+            }
 
             block
                 .add_to_send([
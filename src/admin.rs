@@ -0,0 +1,197 @@
+use std::{fs::Permissions, io, os::unix::fs::PermissionsExt, path::PathBuf};
+
+use anyhow::{anyhow, Context};
+use ethers::providers::Middleware;
+use futures::TryFutureExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tokio::{
+    fs,
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use sandwitch_engine::{control::EngineHandle, limits::SpendingLimitsConfig};
+
+#[derive(Deserialize, Debug)]
+pub struct AdminConfig {
+    /// Unix socket to accept newline-delimited JSON requests on
+    pub socket: PathBuf,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+enum Method {
+    /// Current nonce, balances, last blocks and in-flight transactions
+    Status,
+    Pause,
+    Resume,
+    EnableMonitor { name: String },
+    DisableMonitor { name: String },
+    /// Replace our first stuck transaction with an empty one
+    CancelStuckTx,
+    ResetCircuitBreakers,
+    Limits,
+    /// Change only given spending limits, `null` removes a limit
+    SetLimits(Map<String, Value>),
+}
+
+#[derive(Deserialize, Debug)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    #[serde(flatten)]
+    method: Method,
+}
+
+#[derive(Serialize, Debug)]
+struct Response {
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Response {
+    fn new(id: Value, result: anyhow::Result<Value>) -> Self {
+        match result {
+            Ok(result) => Self {
+                id,
+                result: Some(result),
+                error: None,
+            },
+            Err(err) => Self {
+                id,
+                result: None,
+                error: Some(format!("{err:#}")),
+            },
+        }
+    }
+}
+
+/// Serves admin requests until cancelled
+pub async fn serve<M>(
+    cfg: AdminConfig,
+    engine: EngineHandle<M>,
+    cancel: CancellationToken,
+) -> anyhow::Result<()>
+where
+    M: Middleware + 'static,
+    M::Error: 'static,
+{
+    // socket left by previous run would fail the bind
+    match fs::remove_file(&cfg.socket).await {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            return Err(err).context("failed to remove stale admin socket")
+        }
+        _ => {}
+    }
+    let listener = UnixListener::bind(&cfg.socket).with_context(|| {
+        format!("failed to bind admin socket '{}'", cfg.socket.display())
+    })?;
+    // only the owner may control the bot
+    fs::set_permissions(&cfg.socket, Permissions::from_mode(0o600))
+        .await
+        .context("failed to restrict admin socket permissions")?;
+    info!(socket = %cfg.socket.display(), "admin API is listening");
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            conn = listener.accept() => match conn {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_connection(stream, engine.clone()).inspect_err(|err| {
+                        warn!(%err, "admin connection failed");
+                    }));
+                }
+                Err(err) => error!(%err, "failed to accept admin connection"),
+            },
+        }
+    }
+
+    let _ = fs::remove_file(&cfg.socket).await;
+    Ok(())
+}
+
+async fn handle_connection<M>(stream: UnixStream, engine: EngineHandle<M>) -> io::Result<()>
+where
+    M: Middleware,
+    M::Error: 'static,
+{
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(Request { id, method }) => {
+                debug!(?method, "admin request");
+                Response::new(id, handle(&engine, method).await)
+            }
+            Err(err) => Response::new(Value::Null, Err(anyhow!("invalid request: {err}"))),
+        };
+        let mut response = serde_json::to_vec(&response)?;
+        response.push(b'\n');
+        writer.write_all(&response).await?;
+    }
+    Ok(())
+}
+
+async fn handle<M>(engine: &EngineHandle<M>, method: Method) -> anyhow::Result<Value>
+where
+    M: Middleware,
+    M::Error: 'static,
+{
+    let control = engine.control();
+    Ok(match method {
+        Method::Status => {
+            let mut status = serde_json::to_value(control.status())?;
+            status["paused"] = json!(control.is_paused());
+            status["disabled_monitors"] = json!(control.disabled_monitors());
+            status["tripped_circuit_breakers"] = json!(engine.circuit_breakers().tripped());
+            status
+        }
+        Method::Pause => {
+            control.pause();
+            Value::Null
+        }
+        Method::Resume => {
+            control.resume();
+            Value::Null
+        }
+        Method::EnableMonitor { name } => {
+            control.set_monitor_enabled(&name, true);
+            Value::Null
+        }
+        Method::DisableMonitor { name } => {
+            control.set_monitor_enabled(&name, false);
+            Value::Null
+        }
+        Method::CancelStuckTx => json!(engine.cancel_stuck_tx().await?),
+        Method::ResetCircuitBreakers => {
+            engine.circuit_breakers().reset_all();
+            Value::Null
+        }
+        Method::Limits => serde_json::to_value(engine.limits().config())?,
+        Method::SetLimits(changes) => {
+            let Value::Object(mut limits) = serde_json::to_value(engine.limits().config())? else {
+                unreachable!("limits are serialized as map");
+            };
+            // only the limits themselves are allowed to be changed at runtime
+            for (name, value) in changes {
+                let Some(limit) = limits.get_mut(&name) else {
+                    return Err(anyhow!("unknown limit '{name}'"));
+                };
+                *limit = value;
+            }
+            let limits: SpendingLimitsConfig = serde_json::from_value(Value::Object(limits))?;
+            info!(?limits, "spending limits changed");
+            engine.limits().set_config(limits);
+            serde_json::to_value(engine.limits().config())?
+        }
+    })
+}
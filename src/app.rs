@@ -2,11 +2,12 @@ use core::time::Duration;
 
 use std::sync::Arc;
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use ethers::{
     core::k256::ecdsa::SigningKey,
     providers::{JsonRpcClient, Middleware, Provider, PubsubClient},
     signers::LocalWallet,
+    types::Address,
     utils::secret_key_to_address,
 };
 use futures::{
@@ -17,22 +18,41 @@ use futures::{
 use metrics::register_counter;
 use sandwitch_monitor_erc20::PancakeMonitor;
 use tokio_util::sync::CancellationToken;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 
 use sandwitch_engine::{
-    monitor::{BlockMonitor, MultiMonitor, NoopMonitor},
-    providers::LatencyProvider,
+    breaker::CircuitBreakers,
+    control::{Control, EngineHandle},
+    monitor::{BlockMonitor, MultiMonitor, Named, NoopMonitor, Swappable},
+    pending::PendingView,
+    providers::{CacheProvider, LatencyProvider},
     Engine, MiddlewareStack,
 };
 
-use crate::{providers::timeout::TimeoutProvider, AppConfig, MonitorsConfig};
+use crate::{
+    admin::{self, AdminConfig},
+    layers::ConfigLayers,
+    providers::{instrumented::InstrumentedProvider, timeout::TimeoutProvider},
+    reload::Reloader,
+    AppConfig, MonitorsConfig,
+};
+
+/// Requests are instrumented including the ones timed out,
+/// responses served from cache are not
+pub(crate) type AppProvider<P> = CacheProvider<InstrumentedProvider<TimeoutProvider<P>>>;
+
+pub(crate) type AppMonitor<P> = Box<dyn BlockMonitor<MiddlewareStack<AppProvider<P>>>>;
 
 pub struct App<P>
 where
     P: JsonRpcClient,
     P::Error: 'static,
 {
-    engine: Engine<TimeoutProvider<P>, Box<dyn BlockMonitor<MiddlewareStack<TimeoutProvider<P>>>>>,
+    client: Arc<MiddlewareStack<AppProvider<P>>>,
+    engine: Engine<AppProvider<P>, Swappable<AppMonitor<P>>>,
+    monitor: Swappable<AppMonitor<P>>,
+    admin: Option<AdminConfig>,
+    chain_id: u64,
 }
 
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
@@ -44,11 +64,11 @@ where
     pub async fn new(
         client: P,
         signing_key: impl Into<Option<SigningKey>>,
+        pending_views: Vec<Arc<dyn PendingView>>,
         cfg: AppConfig,
     ) -> anyhow::Result<Self> {
-        let client = Arc::new(Provider::new(LatencyProvider::new(TimeoutProvider::new(
-            client,
-            CLIENT_TIMEOUT,
+        let client = Arc::new(Provider::new(LatencyProvider::new(CacheProvider::new(
+            InstrumentedProvider::new(TimeoutProvider::new(client, CLIENT_TIMEOUT)),
         ))));
         info!("initializing...");
         let (network_id, chain_id, client_version) = try_join!(
@@ -57,6 +77,13 @@ where
             client.client_version(),
         )?;
         info!(network_id, chain_id, client_version, "node info");
+        if let Some(chain) = cfg.chain.filter(|chain| chain.id() != chain_id) {
+            return Err(anyhow!(
+                "config is for `{}` with chain id {}, but node is on chain {chain_id}",
+                chain.name(),
+                chain.id(),
+            ));
+        }
         register_counter!(
             "sandwitch_info",
             "network_id" => network_id,
@@ -65,26 +92,31 @@ where
         )
         .absolute(1);
 
-        let monitor = Self::make_monitor(client.clone(), cfg.monitors).await?;
+        let monitor = Swappable::new(Self::make_monitor(client.clone(), cfg.monitors).await?);
 
         Ok(Self {
             engine: Engine::new(
-                client,
+                client.clone(),
                 cfg.engine,
                 signing_key.into().map(|signing_key| {
                     let address = secret_key_to_address(&signing_key);
                     LocalWallet::new_with_signer(signing_key, address, chain_id)
                 }),
-                monitor,
+                monitor.clone(),
             )
-            .await?,
+            .await?
+            .with_pending_views(pending_views),
+            client,
+            monitor,
+            admin: cfg.admin,
+            chain_id,
         })
     }
 
-    async fn make_monitor(
-        client: impl Into<Arc<MiddlewareStack<TimeoutProvider<P>>>>,
+    pub(crate) async fn make_monitor(
+        client: impl Into<Arc<MiddlewareStack<AppProvider<P>>>>,
         config: MonitorsConfig,
-    ) -> anyhow::Result<Box<dyn BlockMonitor<MiddlewareStack<TimeoutProvider<P>>>>> {
+    ) -> anyhow::Result<AppMonitor<P>> {
         let monitors = Self::make_monitors(client, config).await?;
         Ok(match monitors.len() {
             0 => {
@@ -98,9 +130,9 @@ where
 
     #[allow(unused_variables)]
     async fn make_monitors(
-        client: impl Into<Arc<MiddlewareStack<TimeoutProvider<P>>>>,
+        client: impl Into<Arc<MiddlewareStack<AppProvider<P>>>>,
         cfg: MonitorsConfig,
-    ) -> anyhow::Result<MultiMonitor<Box<dyn BlockMonitor<MiddlewareStack<TimeoutProvider<P>>>>>>
+    ) -> anyhow::Result<MultiMonitor<Box<dyn BlockMonitor<MiddlewareStack<AppProvider<P>>>>>>
     {
         let client = client.into();
         let ms = FuturesUnordered::<LocalBoxFuture<_>>::new();
@@ -116,10 +148,10 @@ where
         }
 
         #[cfg(feature = "pancake_swap")]
-        if let Some(cfg) = cfg.pancake_swap {
+        if let Some(cfg) = cfg.uniswap_v2 {
             ms.push(
                 PancakeMonitor::from_config(client.clone(), cfg)
-                    .map_ok(|m| Box::new(m) as Box<dyn BlockMonitor<_>>)
+                    .map_ok(|m| Box::new(Named::new("uniswap_v2", m)) as Box<dyn BlockMonitor<_>>)
                     .map(|r| r.context("pancake"))
                     .boxed_local(),
             );
@@ -127,7 +159,45 @@ where
 
         ms.try_collect().await
     }
+
+    pub fn circuit_breakers(&self) -> Arc<CircuitBreakers> {
+        self.engine.circuit_breakers()
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    pub fn account(&self) -> Address {
+        self.engine.account()
+    }
+
+    pub fn control(&self) -> Arc<Control> {
+        self.engine.control()
+    }
+
+    pub fn handle(&self) -> EngineHandle<MiddlewareStack<AppProvider<P>>> {
+        self.engine.handle()
+    }
+
+    /// Reloader of config read from `path`, which is currently `current`
+    pub fn reloader(&self, layers: ConfigLayers, current: toml::Value) -> Reloader<P> {
+        Reloader::new(
+            layers,
+            current,
+            self.client.clone(),
+            self.monitor.clone(),
+            self.engine.handle(),
+        )
+    }
+
     pub async fn run(self, cancel: CancellationToken) -> anyhow::Result<()> {
+        if let Some(cfg) = self.admin {
+            tokio::spawn(
+                admin::serve(cfg, self.engine.handle(), cancel.child_token())
+                    .inspect_err(|err| error!(%err, "admin API failed")),
+            );
+        }
         self.engine.run(cancel).await
     }
 }
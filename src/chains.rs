@@ -0,0 +1,143 @@
+use core::time::Duration;
+
+use ethers::providers::{Middleware, Provider};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::NetworkConfig;
+
+/// Built-in chain profile, selected by `chain` in config or detected
+/// from `eth_chainId` of the node if not set
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Chain {
+    Bsc,
+    BscTestnet,
+    Ethereum,
+    Sepolia,
+    Anvil,
+}
+
+impl Chain {
+    const ALL: [Self; 5] = [
+        Self::Bsc,
+        Self::BscTestnet,
+        Self::Ethereum,
+        Self::Sepolia,
+        Self::Anvil,
+    ];
+
+    pub fn id(self) -> u64 {
+        match self {
+            Self::Bsc => 56,
+            Self::BscTestnet => 97,
+            Self::Ethereum => 1,
+            Self::Sepolia => 11_155_111,
+            Self::Anvil => 31_337,
+        }
+    }
+
+    pub fn from_id(id: u64) -> Option<Self> {
+        Self::ALL.into_iter().find(|chain| chain.id() == id)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bsc => "bsc",
+            Self::BscTestnet => "bsc-testnet",
+            Self::Ethereum => "ethereum",
+            Self::Sepolia => "sepolia",
+            Self::Anvil => "anvil",
+        }
+    }
+
+    /// Defaults of the chain, which config values take precedence over
+    pub fn preset(self) -> toml::Table {
+        let preset = match self {
+            Self::Bsc => BSC,
+            Self::BscTestnet => BSC_TESTNET,
+            Self::Ethereum => ETHEREUM,
+            Self::Sepolia => SEPOLIA,
+            Self::Anvil => ANVIL,
+        };
+        toml::from_str(preset).expect("chain presets are valid TOML")
+    }
+}
+
+const BSC: &str = r#"
+[engine]
+block_interval_ms = 3_000
+tx_type = "legacy"
+base_token = "0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c" # WBNB
+
+[monitors.uniswap_v2]
+router = "0x10ED43C718714eb63d5aA57B78B54704E256024E"     # PancakeSwap v2
+base_token = "0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c" # WBNB
+"#;
+
+const BSC_TESTNET: &str = r#"
+[engine]
+block_interval_ms = 3_000
+tx_type = "legacy"
+base_token = "0xae13d989daC2f0dEbFf460aC112a837C89BAa7cd" # WBNB
+
+[monitors.uniswap_v2]
+router = "0x9Ac64Cc6e4415144C455BD8E4837Fea55603e5c3"     # PancakeSwap v2
+base_token = "0xae13d989daC2f0dEbFf460aC112a837C89BAa7cd" # WBNB
+"#;
+
+const ETHEREUM: &str = r#"
+[engine]
+block_interval_ms = 12_000
+base_token = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2" # WETH
+
+[monitors.uniswap_v2]
+router = "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D"     # Uniswap v2
+base_token = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2" # WETH
+"#;
+
+const SEPOLIA: &str = r#"
+[engine]
+block_interval_ms = 12_000
+base_token = "0xfFf9976782d46CC05630D1f6eBAb18b2324d6B14" # WETH
+
+[monitors.uniswap_v2]
+router = "0xeE567Fe1712Faf6149d80dA1E6934E354124CfE3"     # Uniswap v2
+base_token = "0xfFf9976782d46CC05630D1f6eBAb18b2324d6B14" # WETH
+"#;
+
+/// `anvil --block-time 1`
+const ANVIL: &str = r#"
+[engine]
+block_interval_ms = 1_000
+"#;
+
+/// Detects the chain by `eth_chainId` of the node,
+/// [`None`] if there is no preset for it
+pub async fn detect(network: &NetworkConfig) -> anyhow::Result<Option<Chain>> {
+    // nothing is subscribed to, so block interval does not matter
+    let client = Provider::new(network.connect(Duration::from_secs(1)).await?);
+    let chain_id = client.get_chainid().await?.as_u64();
+    let chain = Chain::from_id(chain_id);
+    match chain {
+        Some(chain) => info!(chain = chain.name(), "chain detected"),
+        None => warn!(chain_id, "no preset for the chain of the node"),
+    }
+    Ok(chain)
+}
+
+/// Fills values missing in `config` from `preset`, but only within tables
+/// present in config, so that optional sections are not enabled by presets
+pub(crate) fn fill_defaults(config: &mut toml::Table, preset: toml::Table) {
+    for (key, default) in preset {
+        match (config.get_mut(&key), default) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(default)) => {
+                fill_defaults(table, default)
+            }
+            (Some(_), _) | (None, toml::Value::Table(_)) => {}
+            (None, default) => {
+                config.insert(key, default);
+            }
+        }
+    }
+}
@@ -1,16 +1,43 @@
-use std::path::PathBuf;
+use core::time::Duration;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use anyhow::anyhow;
-use ethers::{prelude::k256::ecdsa::SigningKey, providers::PubsubClient};
+use anyhow::{anyhow, Context};
+use ethers::{
+    middleware::SignerMiddleware,
+    providers::{Http, Middleware, Provider, PubsubClient},
+    signers::LocalWallet,
+    types::BlockNumber,
+};
 use impl_tools::autoimpl;
 use sandwitch_monitor_erc20::PancakeConfig;
 use serde::Deserialize;
-use tracing::info;
+use serde_with::{serde_as, DurationMilliSeconds, OneOrMany};
+use tracing::{info, warn};
 use url::Url;
 
-use sandwitch_engine::config::Config as EngineConfig;
+use sandwitch_engine::{config::Config as EngineConfig, pending::PendingView};
 
-use crate::{providers::one_of::OneOf, App};
+use crate::{
+    admin::AdminConfig,
+    chains::Chain,
+    health::HealthConfig,
+    keystore,
+    providers::{
+        batch::{BatchConfig, BatchingProvider},
+        failover::FailoverProvider,
+        one_of::OneOf,
+        polling::PollingProvider,
+        rate_limit::{RateLimitConfig, RateLimitProvider},
+        record::{Recorder, RecordingProvider},
+        retry::{RetryConfig, RetryProvider},
+    },
+    App,
+};
+#[cfg(all(feature = "ws", feature = "ipc"))]
+use crate::providers::one_of::OneOf3;
 
 #[derive(Deserialize)]
 #[autoimpl(Deref using self.app)]
@@ -21,24 +48,107 @@ pub struct Config {
 }
 
 impl Config {
+    /// Connects to the node, optionally recording everything received
+    /// from it into `record` file
     pub async fn init(
         self,
         keystore_password: impl Into<Option<String>>,
+        record: Option<&Path>,
     ) -> anyhow::Result<App<impl PubsubClient>> {
+        info!("connecting to node...");
+        let client = self.network.connect(self.engine.block_interval).await?;
+        info!("connected to node");
+        let pending_views = if self.network.merge_pending {
+            client.pending_views()
+        } else {
+            Vec::new()
+        };
+        let client = RetryProvider::new(client, self.network.retry);
+
+        let client = match record {
+            Some(path) => OneOf::P1(RecordingProvider::new(
+                client,
+                Recorder::create(path)
+                    .with_context(|| format!("failed to create recording '{}'", path.display()))?,
+            )),
+            None => OneOf::P2(client),
+        };
+        self.init_with_views(client, pending_views, keystore_password)
+            .await
+    }
+
+    /// Connects to the node with client signing transactions with the key
+    /// from keystore, which is required in this case
+    pub async fn signing_client(
+        &self,
+        keystore_password: impl Into<Option<String>>,
+    ) -> anyhow::Result<Arc<SignerMiddleware<Provider<impl PubsubClient>, LocalWallet>>> {
+        let keystore = self
+            .keystore
+            .as_ref()
+            .ok_or_else(|| anyhow!("keystore is not set in config"))?;
+        let password = keystore_password
+            .into()
+            .ok_or_else(|| anyhow!("keystore password is required"))?;
+        let wallet = LocalWallet::from(keystore::signing_key(&keystore::decrypt(
+            &keystore.path,
+            &password,
+        )?)?);
+
+        info!("connecting to node...");
+        let client = Provider::new(RetryProvider::new(
+            self.network.connect(self.engine.block_interval).await?,
+            self.network.retry,
+        ));
+        Ok(Arc::new(
+            SignerMiddleware::new_with_provider_chain(client, wallet).await?,
+        ))
+    }
+
+    /// Whether commands send legacy transactions, according to
+    /// `engine.tx_type` and the latest block of the node
+    pub async fn is_legacy<M>(&self, client: &M) -> anyhow::Result<bool>
+    where
+        M: Middleware,
+        M::Error: 'static,
+    {
+        let head = client.get_block(BlockNumber::Latest).await?;
+        Ok(self
+            .engine
+            .tx_type
+            .is_legacy(head.and_then(|head| head.base_fee_per_gas)))
+    }
+
+    pub async fn init_with_client<P>(
+        self,
+        client: P,
+        keystore_password: impl Into<Option<String>>,
+    ) -> anyhow::Result<App<P>>
+    where
+        P: PubsubClient + 'static,
+    {
+        self.init_with_views(client, Vec::new(), keystore_password)
+            .await
+    }
+
+    async fn init_with_views<P>(
+        self,
+        client: P,
+        pending_views: Vec<Arc<dyn PendingView>>,
+        keystore_password: impl Into<Option<String>>,
+    ) -> anyhow::Result<App<P>>
+    where
+        P: PubsubClient + 'static,
+    {
         App::new(
-            {
-                info!("connecting to node...");
-                let client = self.network.connect().await?;
-                info!("connected to node");
-                client
-            },
+            client,
             self.keystore
                 .zip(keystore_password.into())
                 .map(|(keystore, keystore_password)| {
-                    let secret = eth_keystore::decrypt_key(keystore.path, keystore_password)?;
-                    anyhow::Ok(SigningKey::from_bytes(secret.as_slice().into())?)
+                    keystore::signing_key(&keystore::decrypt(keystore.path, &keystore_password)?)
                 })
                 .transpose()?,
+            pending_views,
             self.app,
         )
         .await
@@ -47,9 +157,27 @@ impl Config {
 
 #[derive(Deserialize, Debug)]
 pub struct AppConfig {
+    /// Chain the preset was applied for, node is verified to be on it
+    pub chain: Option<Chain>,
     pub network: NetworkConfig,
     pub engine: EngineConfig,
     pub monitors: MonitorsConfig,
+    /// Control API of the running bot, disabled if not set
+    pub admin: Option<AdminConfig>,
+    /// Liveness and readiness endpoints, disabled if not set
+    pub health: Option<HealthConfig>,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+}
+
+/// Metrics backends, each one is enabled by setting its address,
+/// CLI flags take precedence
+#[derive(Deserialize, Debug, Default)]
+pub struct MetricsConfig {
+    /// HOST:PORT to serve Prometheus metrics at
+    pub listen: Option<String>,
+    /// Endpoint to export OTLP metrics to
+    pub otlp_endpoint: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -57,48 +185,144 @@ pub struct KeyStore {
     pub path: PathBuf,
 }
 
+#[serde_as]
 #[derive(Deserialize, Debug)]
 pub struct NetworkConfig {
-    pub node: Url,
+    /// Nodes in order of priority, requests fail over to the next ones
+    #[serde(alias = "node")]
+    #[serde_as(as = "OneOrMany<_>")]
+    pub nodes: Vec<Url>,
+
+    /// Timeout of a single request to a node before failing over
+    #[serde(rename = "node_timeout_ms")]
+    #[serde(default = "NetworkConfig::default_node_timeout")]
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub node_timeout: Duration,
+
+    /// Merge pending blocks of all nodes instead of taking the one
+    /// of the node requests are routed to
+    #[serde(default)]
+    pub merge_pending: bool,
+
+    /// Retries of requests failed with transient errors
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    /// Limit of requests to each of nodes, unlimited if not set
+    pub rate_limit: Option<RateLimitConfig>,
+
+    /// Batching of requests to `http(s)://` nodes, disabled if not set
+    pub batch: Option<BatchConfig>,
 }
 
 impl NetworkConfig {
+    fn default_node_timeout() -> Duration {
+        Duration::from_secs(2)
+    }
+
+    /// Connects to all of the nodes skipping unavailable ones
+    pub async fn connect(
+        &self,
+        block_interval: Duration,
+    ) -> anyhow::Result<FailoverProvider<impl PubsubClient>> {
+        let is_http = |url: &Url| matches!(url.scheme(), "http" | "https");
+        if self.batch.is_some() && !self.nodes.iter().any(is_http) {
+            warn!("batching is set, but has no effect without `http(s)://` nodes");
+        }
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        for url in &self.nodes {
+            let name = node_name(url);
+            match self.connect_node(url, block_interval).await {
+                Ok(client) => nodes.push((name, RateLimitProvider::new(client, self.rate_limit))),
+                Err(err) => warn!(node = name, "failed to connect to node: {err:#}"),
+            }
+        }
+        if nodes.is_empty() {
+            return Err(anyhow!("failed to connect to any of {} nodes", self.nodes.len()));
+        }
+        Ok(FailoverProvider::new(nodes, self.node_timeout))
+    }
+
+    /// Connects to the node, `http(s)://` ones are polled for new heads
+    /// with interval tuned starting from `block_interval`
     #[cfg(all(feature = "ipc", not(feature = "ws")))]
-    pub async fn connect(&self) -> anyhow::Result<impl PubsubClient> {
-        self.connect_ipc().await
+    async fn connect_node(
+        &self,
+        url: &Url,
+        block_interval: Duration,
+    ) -> anyhow::Result<impl PubsubClient> {
+        Ok(match url.scheme() {
+            "file" => OneOf::P1(Self::connect_ipc(url).await?),
+            "http" | "https" => OneOf::P2(self.connect_http(url, block_interval)),
+            _ => return Err(anyhow!("invalid node url: {url}")),
+        })
     }
 
+    /// Connects to the node, `http(s)://` ones are polled for new heads
+    /// with interval tuned starting from `block_interval`
     #[cfg(all(feature = "ws", not(feature = "ipc")))]
-    pub async fn connect(&self) -> anyhow::Result<impl PubsubClient> {
-        self.connect_ws().await
+    async fn connect_node(
+        &self,
+        url: &Url,
+        block_interval: Duration,
+    ) -> anyhow::Result<impl PubsubClient> {
+        Ok(match url.scheme() {
+            "ws" | "wss" => OneOf::P1(Self::connect_ws(url).await?),
+            "http" | "https" => OneOf::P2(self.connect_http(url, block_interval)),
+            _ => return Err(anyhow!("invalid node url: {url}")),
+        })
     }
 
+    /// Connects to the node, `http(s)://` ones are polled for new heads
+    /// with interval tuned starting from `block_interval`
     #[cfg(all(feature = "ws", feature = "ipc"))]
-    pub async fn connect(&self) -> anyhow::Result<impl PubsubClient> {
-        Ok(match self.node.scheme() {
-            "ws" | "wss" => OneOf::P1(self.connect_ws().await?),
-            "file" => OneOf::P2(self.connect_ipc().await?),
-            _ => return Err(anyhow!("invalid node url: {}", self.node)),
+    async fn connect_node(
+        &self,
+        url: &Url,
+        block_interval: Duration,
+    ) -> anyhow::Result<impl PubsubClient> {
+        Ok(match url.scheme() {
+            "ws" | "wss" => OneOf3::P1(Self::connect_ws(url).await?),
+            "file" => OneOf3::P2(Self::connect_ipc(url).await?),
+            "http" | "https" => OneOf3::P3(self.connect_http(url, block_interval)),
+            _ => return Err(anyhow!("invalid node url: {url}")),
         })
     }
 
     #[cfg(feature = "ipc")]
-    async fn connect_ipc(&self) -> anyhow::Result<impl PubsubClient> {
-        ethers::providers::Ipc::connect(
-            self.node
-                .to_file_path()
-                .map_err(|_| anyhow!("invalid IPC url"))?,
-        )
-        .await
-        .map_err(Into::into)
+    async fn connect_ipc(url: &Url) -> anyhow::Result<impl PubsubClient> {
+        ethers::providers::Ipc::connect(url.to_file_path().map_err(|_| anyhow!("invalid IPC url"))?)
+            .await
+            .map_err(Into::into)
     }
 
     #[cfg(feature = "ws")]
-    async fn connect_ws(&self) -> anyhow::Result<impl PubsubClient> {
-        ethers::providers::Ws::connect(&self.node)
+    async fn connect_ws(url: &Url) -> anyhow::Result<impl PubsubClient> {
+        ethers::providers::Ws::connect(url)
             .await
             .map_err(Into::into)
     }
+
+    /// Requests are batched if `batch` is set
+    fn connect_http(&self, url: &Url, block_interval: Duration) -> impl PubsubClient {
+        PollingProvider::new(
+            match self.batch {
+                Some(cfg) => OneOf::P1(BatchingProvider::new(url.clone(), cfg)),
+                None => OneOf::P2(Http::new(url.clone())),
+            },
+            block_interval,
+        )
+    }
+}
+
+/// Name of the node in logs and metrics without credentials, which are
+/// often part of the path
+fn node_name(url: &Url) -> String {
+    match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_string(),
+        (None, _) => url.path().to_string(),
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -113,6 +337,7 @@ pub struct MonitorsConfig {
     #[cfg(feature = "tx_logger")]
     pub tx_logger: MonitorConfig<()>,
     // pub tx_logger:
+    /// Uniswap v2 style routers, such as PancakeSwap v2 one
     #[cfg(feature = "pancake_swap")]
-    pub pancake_swap: Option<PancakeConfig>,
+    pub uniswap_v2: Option<PancakeConfig>,
 }
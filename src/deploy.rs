@@ -0,0 +1,227 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::{anyhow, Context};
+use clap::{Args, Subcommand, ValueEnum};
+use ethers::{
+    contract::FunctionCall,
+    providers::Middleware,
+    types::{Address, TransactionReceipt},
+};
+use tokio::fs;
+use tracing::info;
+
+use sandwitch_contracts::multicall::MultiCallContract;
+#[cfg(feature = "pancake_swap")]
+use sandwitch_contracts::{pancake_swap::router::PancakeRouter, pancake_toaster::PancakeToaster};
+
+use crate::Config;
+
+#[derive(Subcommand)]
+pub enum DeployCommand {
+    /// Deploy `OwnedMultiCall` owned by the keystore account
+    Multicall {
+        #[command(flatten)]
+        args: DeployArgs,
+    },
+    /// Deploy `PancakeToaster` owned by the keystore account
+    #[cfg(feature = "pancake_swap")]
+    Toaster {
+        /// PancakeSwap factory, the one of configured router if not set
+        #[arg(long)]
+        factory: Option<Address>,
+        #[command(flatten)]
+        args: DeployArgs,
+    },
+}
+
+#[derive(Args)]
+pub struct DeployArgs {
+    /// Number of blocks to wait for after the transaction is included
+    #[arg(long, default_value_t = 1)]
+    confirmations: usize,
+    /// Write address of deployed contract back to the config file
+    #[arg(long)]
+    write_config: bool,
+}
+
+#[derive(Subcommand)]
+pub enum AdminCommand {
+    /// Transfer ownership of the contract to another account
+    TransferOwnership {
+        #[arg(value_enum)]
+        contract: Owned,
+        new_owner: Address,
+        /// Address of the contract, the configured one if not set
+        #[arg(long)]
+        address: Option<Address>,
+        /// Number of blocks to wait for after the transaction is included
+        #[arg(long, default_value_t = 1)]
+        confirmations: usize,
+    },
+}
+
+/// Contracts owned by our account
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Owned {
+    Multicall,
+    #[cfg(feature = "pancake_swap")]
+    Toaster,
+}
+
+impl Owned {
+    /// Path of contract address in the config file
+    fn config_path(self) -> &'static [&'static str] {
+        match self {
+            Self::Multicall => &["engine", "multicall"],
+            #[cfg(feature = "pancake_swap")]
+            Self::Toaster => &["monitors", "uniswap_v2", "toaster"],
+        }
+    }
+
+    fn configured(self, config: &Config) -> Option<Address> {
+        match self {
+            Self::Multicall => Some(config.engine.multicall),
+            #[cfg(feature = "pancake_swap")]
+            Self::Toaster => config.monitors.uniswap_v2.as_ref().map(|cfg| cfg.toaster),
+        }
+    }
+}
+
+impl DeployCommand {
+    pub async fn run(
+        self,
+        config: &Config,
+        config_path: &Path,
+        keystore_password: Option<String>,
+    ) -> anyhow::Result<()> {
+        let client = config.signing_client(keystore_password).await?;
+        let legacy = config.is_legacy(client.as_ref()).await?;
+        let (contract, args, address, owner) = match self {
+            Self::Multicall { args } => {
+                info!("deploying multicall...");
+                let (multicall, receipt) =
+                    MultiCallContract::deploy(client.clone(), args.confirmations, legacy).await?;
+                log_receipt(&receipt);
+                (Owned::Multicall, args, multicall.address(), multicall.owner())
+            }
+            #[cfg(feature = "pancake_swap")]
+            Self::Toaster { factory, args } => {
+                let factory = match factory {
+                    Some(factory) => factory,
+                    None => {
+                        let router = config
+                            .monitors
+                            .uniswap_v2
+                            .as_ref()
+                            .ok_or_else(|| anyhow!("neither factory nor router is set"))?
+                            .router;
+                        PancakeRouter::new(router, client.clone()).factory().await?
+                    }
+                };
+                info!(?factory, "deploying toaster...");
+                let mut deployer = PancakeToaster::deploy(client.clone(), factory)?
+                    .confirmations(args.confirmations);
+                if legacy {
+                    deployer = deployer.legacy();
+                }
+                let (toaster, receipt) = deployer.send_with_receipt().await?;
+                log_receipt(&receipt);
+                (Owned::Toaster, args, toaster.address(), toaster.owner())
+            }
+        };
+        check_owner(owner, client.address()).await?;
+        println!("{address:?}");
+
+        if args.write_config {
+            write_address(config_path, contract.config_path(), address).await?;
+            info!(config = %config_path.display(), "address written to config");
+        }
+        Ok(())
+    }
+}
+
+impl AdminCommand {
+    pub async fn run(
+        self,
+        config: &Config,
+        keystore_password: Option<String>,
+    ) -> anyhow::Result<()> {
+        match self {
+            Self::TransferOwnership {
+                contract,
+                new_owner,
+                address,
+                confirmations,
+            } => {
+                let address = address
+                    .or_else(|| contract.configured(config))
+                    .ok_or_else(|| anyhow!("contract address is not set"))?;
+                let client = config.signing_client(keystore_password).await?;
+                let (owner, mut transfer) = match contract {
+                    Owned::Multicall => {
+                        let multicall = MultiCallContract::new(address, client.clone());
+                        (multicall.owner(), multicall.transfer_ownership(new_owner))
+                    }
+                    #[cfg(feature = "pancake_swap")]
+                    Owned::Toaster => {
+                        let toaster = PancakeToaster::new(address, client.clone());
+                        (toaster.owner(), toaster.transfer_ownership(new_owner))
+                    }
+                };
+                check_owner(owner.clone(), client.address()).await?;
+
+                if config.is_legacy(client.as_ref()).await? {
+                    transfer = transfer.legacy();
+                }
+                info!(?address, ?new_owner, "transferring ownership...");
+                let receipt = transfer
+                    .send()
+                    .await?
+                    .confirmations(confirmations)
+                    .await?
+                    .ok_or_else(|| anyhow!("transaction was dropped"))?;
+                log_receipt(&receipt);
+                if receipt.status != Some(1.into()) {
+                    return Err(anyhow!("transaction {:?} reverted", receipt.transaction_hash));
+                }
+                check_owner(owner, new_owner).await
+            }
+        }
+    }
+}
+
+async fn check_owner<M: Middleware>(
+    owner: FunctionCall<Arc<M>, M, Address>,
+    expected: Address,
+) -> anyhow::Result<()> {
+    let owner = owner.call().await?;
+    if owner != expected {
+        return Err(anyhow!("owner is {owner:?} instead of {expected:?}"));
+    }
+    Ok(())
+}
+
+fn log_receipt(receipt: &TransactionReceipt) {
+    info!(
+        tx_hash = ?receipt.transaction_hash,
+        block.number = receipt.block_number.map(|n| n.as_u64()),
+        gas_used = receipt.gas_used.map(|g| g.as_u64()),
+        "transaction included",
+    );
+}
+
+/// Sets the address at given path in TOML config preserving its formatting
+async fn write_address(path: &Path, keys: &[&str], address: Address) -> anyhow::Result<()> {
+    let mut config: toml_edit::Document = fs::read_to_string(path)
+        .await
+        .with_context(|| format!("failed to read config file '{}'", path.display()))?
+        .parse()
+        .with_context(|| format!("failed to parse TOML config file '{}'", path.display()))?;
+    let item = keys
+        .iter()
+        .fold(config.as_item_mut(), |item, key| &mut item[*key]);
+    *item = toml_edit::value(format!("{address:?}"));
+    fs::write(path, config.to_string())
+        .await
+        .with_context(|| format!("failed to write config file '{}'", path.display()))
+}
@@ -0,0 +1,271 @@
+use core::convert::Infallible;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context};
+use clap::Subcommand;
+use ethers::{
+    providers::{Middleware, PendingTransaction},
+    types::{Address, U256},
+    utils::{format_ether, format_units, parse_ether, parse_units},
+};
+use futures::future::try_join_all;
+use tracing::info;
+
+use sandwitch_contracts::{
+    erc20::{ApproveCall, BalanceOfCall, BalanceOfReturn, TransferCall, ERC20},
+    multicall::{
+        Call, Calls, ContractCall, DynCall, DynCalls, DynTryCall, GetBalanceOf, MultiCallContract,
+        Transfer, TryCall,
+    },
+};
+
+use crate::Config;
+
+#[derive(Subcommand)]
+pub enum FundsCommand {
+    /// Show native and token balances of our account and the multicall
+    Balance {
+        /// ERC20 tokens to show balances of
+        tokens: Vec<Address>,
+    },
+    /// Withdraw funds from the multicall in one transaction
+    Withdraw {
+        /// Recipient, our account if not set
+        #[arg(long)]
+        to: Option<Address>,
+        /// Amount of native currency in ether
+        #[arg(long, value_name = "AMOUNT")]
+        native: Option<String>,
+        /// Amount of ERC20 token in its units
+        #[arg(long = "token", value_name = "TOKEN=AMOUNT", value_parser = parse_token_amount)]
+        tokens: Vec<(Address, String)>,
+    },
+    /// Approve spending tokens held by the multicall
+    Approve {
+        #[arg(long)]
+        spender: Address,
+        /// Amount in token units, unlimited if not set
+        #[arg(long)]
+        amount: Option<String>,
+        tokens: Vec<Address>,
+    },
+    /// Withdraw whole native balance and balances of given tokens
+    /// from the multicall in one transaction
+    Sweep {
+        /// Recipient, our account if not set
+        #[arg(long)]
+        to: Option<Address>,
+        tokens: Vec<Address>,
+    },
+}
+
+/// Number of blocks to wait for after the transaction is included
+const CONFIRMATIONS: usize = 1;
+
+impl FundsCommand {
+    pub async fn run(self, config: &Config, keystore_password: Option<String>) -> anyhow::Result<()> {
+        let client = config.signing_client(keystore_password).await?;
+        let account = client.address();
+        let multicall = MultiCallContract::new(config.engine.multicall, client.clone());
+
+        match self {
+            Self::Balance { tokens } => {
+                let (metas, balances) = futures::try_join!(
+                    try_join_all(tokens.iter().map(|&token| token_meta(&client, token))),
+                    balances(&multicall, account, &tokens),
+                )?;
+                let Balances {
+                    account: (account_native, account_tokens),
+                    multicall: (multicall_native, multicall_tokens),
+                } = balances;
+
+                println!("{:<12}{:>28}{:>28}", "", "account", "multicall");
+                println!(
+                    "{:<12}{:>28}{:>28}",
+                    "native",
+                    format_ether(account_native),
+                    format_ether(multicall_native),
+                );
+                for ((symbol, decimals), (account, multicall)) in
+                    metas.into_iter().zip(account_tokens.into_iter().zip(multicall_tokens))
+                {
+                    println!(
+                        "{:<12}{:>28}{:>28}",
+                        symbol,
+                        format_units(account, decimals)?,
+                        format_units(multicall, decimals)?,
+                    );
+                }
+                Ok(())
+            }
+            Self::Withdraw { to, native, tokens } => {
+                let to = to.unwrap_or(account);
+                let mut calls = DynCalls::new();
+                if let Some(amount) = native {
+                    let amount = parse_ether(&amount)
+                        .with_context(|| format!("invalid amount '{amount}'"))?;
+                    calls.push(must(Transfer::transafer(to, amount)));
+                }
+                for (token, amount) in tokens {
+                    let (_, decimals) = token_meta(&client, token).await?;
+                    let amount: U256 = parse_units(&amount, decimals)
+                        .with_context(|| format!("invalid amount '{amount}'"))?
+                        .into();
+                    calls.push(must(ContractCall::new(token, TransferCall { to, amount })));
+                }
+                send(config, &multicall, calls).await
+            }
+            Self::Approve {
+                spender,
+                amount,
+                tokens,
+            } => {
+                let mut calls = DynCalls::new();
+                for token in tokens {
+                    let amount = match &amount {
+                        Some(amount) => {
+                            let (_, decimals) = token_meta(&client, token).await?;
+                            parse_units(amount, decimals)
+                                .with_context(|| format!("invalid amount '{amount}'"))?
+                                .into()
+                        }
+                        None => U256::MAX,
+                    };
+                    calls.push(must(ContractCall::new(token, ApproveCall { spender, amount })));
+                }
+                send(config, &multicall, calls).await
+            }
+            Self::Sweep { to, tokens } => {
+                let to = to.unwrap_or(account);
+                let Balances {
+                    multicall: (native, token_balances),
+                    ..
+                } = balances(&multicall, account, &tokens).await?;
+
+                let mut calls = DynCalls::new();
+                if !native.is_zero() {
+                    calls.push(must(Transfer::transafer(to, native)));
+                }
+                for (token, amount) in tokens.into_iter().zip(token_balances) {
+                    if !amount.is_zero() {
+                        calls.push(must(ContractCall::new(token, TransferCall { to, amount })));
+                    }
+                }
+                if calls.is_empty() {
+                    info!("nothing to sweep");
+                    return Ok(());
+                }
+                send(config, &multicall, calls).await
+            }
+        }
+    }
+}
+
+/// Native and token balances
+type Holdings = (U256, Vec<U256>);
+
+struct Balances {
+    account: Holdings,
+    multicall: Holdings,
+}
+
+/// Queries all of the balances in a single call to the multicall
+async fn balances<M>(
+    multicall: &MultiCallContract<Arc<M>, M>,
+    account: Address,
+    tokens: &[Address],
+) -> anyhow::Result<Balances>
+where
+    M: Middleware + 'static,
+{
+    let token_balances = |owner: Address| -> Calls<_> {
+        tokens
+            .iter()
+            .map(|&token| ContractCall::new(token, BalanceOfCall(owner)).must().into_dyn())
+            .collect()
+    };
+    let (multicall_native, account_native, multicall_tokens, account_tokens) = multicall
+        .multicall((
+            GetBalanceOf::This.must(),
+            GetBalanceOf::MsgSender.must(),
+            token_balances(multicall.address()).must(),
+            token_balances(account).must(),
+        ))
+        .from(account)
+        .call()
+        .await?
+        .map_err(|_| anyhow!("failed to query balances"))?;
+
+    let token_balances = |balances: Result<Vec<Result<BalanceOfReturn, _>>, Infallible>| {
+        never_fails(balances)
+            .into_iter()
+            .map(|balance| balance.map(|BalanceOfReturn(balance)| balance))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| anyhow!("failed to query token balance: {err}"))
+    };
+    Ok(Balances {
+        account: (never_fails(account_native), token_balances(account_tokens)?),
+        multicall: (never_fails(multicall_native), token_balances(multicall_tokens)?),
+    })
+}
+
+/// Simulates calls and sends them in one transaction on success
+async fn send<M>(
+    config: &Config,
+    multicall: &MultiCallContract<Arc<M>, M>,
+    calls: DynCalls,
+) -> anyhow::Result<()>
+where
+    M: Middleware + 'static,
+{
+    let call = multicall
+        .multicall(calls)
+        .legacy(config.is_legacy(multicall.client()).await?);
+    if call.call().await?.is_err() {
+        return Err(anyhow!("transaction would revert"));
+    }
+    let hash = call.send().await?;
+    info!(?hash, "transaction sent, waiting for confirmation...");
+
+    let receipt = PendingTransaction::new(hash, multicall.client().provider())
+        .confirmations(CONFIRMATIONS)
+        .await?
+        .ok_or_else(|| anyhow!("transaction was dropped"))?;
+    if receipt.status != Some(1.into()) {
+        return Err(anyhow!("transaction {hash:?} reverted"));
+    }
+    println!("{hash:?}");
+    Ok(())
+}
+
+/// Symbol and decimals of the token
+async fn token_meta<M>(client: &Arc<M>, token: Address) -> anyhow::Result<(String, u32)>
+where
+    M: Middleware + 'static,
+{
+    let token = ERC20::new(token, client.clone());
+    let (symbol, decimals) = futures::try_join!(token.symbol().call(), token.decimals().call())
+        .with_context(|| format!("failed to get metadata of token {:?}", token.address()))?;
+    Ok((symbol, decimals.into()))
+}
+
+fn must(call: impl Into<DynCall>) -> DynTryCall<DynCall> {
+    call.into().must().into_dyn()
+}
+
+fn never_fails<T>(result: Result<T, Infallible>) -> T {
+    match result {
+        Ok(v) => v,
+        Err(never) => match never {},
+    }
+}
+
+fn parse_token_amount(s: &str) -> Result<(Address, String), String> {
+    let (token, amount) = s
+        .split_once('=')
+        .ok_or_else(|| "expected TOKEN=AMOUNT".to_string())?;
+    Ok((
+        token.parse().map_err(|err| format!("invalid token: {err}"))?,
+        amount.to_string(),
+    ))
+}
@@ -0,0 +1,182 @@
+use core::time::Duration;
+use std::{
+    io,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{anyhow, Context};
+use futures::TryFutureExt;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{self, TcpListener, TcpStream},
+    time::{timeout, Instant},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info};
+
+use sandwitch_engine::control::Control;
+
+#[serde_as]
+#[derive(Deserialize, Debug)]
+pub struct HealthConfig {
+    /// HOST:PORT to serve `/livez` and `/readyz` at
+    pub listen: String,
+
+    /// Unhealthy if no head was received within this many block intervals
+    #[serde(default = "HealthConfig::default_max_missed_heads")]
+    pub max_missed_heads: u32,
+
+    /// Unhealthy if blocks are skipped due to our stuck
+    /// transactions for longer than this
+    #[serde(rename = "max_stuck_nonce_secs")]
+    #[serde(default = "HealthConfig::default_max_stuck_nonce")]
+    #[serde_as(as = "DurationSeconds")]
+    pub max_stuck_nonce: Duration,
+}
+
+impl HealthConfig {
+    fn default_max_missed_heads() -> u32 {
+        5
+    }
+
+    fn default_max_stuck_nonce() -> Duration {
+        Duration::from_secs(60)
+    }
+}
+
+/// Health of the app, which becomes ready once it is initialized
+#[derive(Default)]
+pub struct Health {
+    /// Engine control along with the time the app became ready
+    ready: RwLock<Option<(Arc<Control>, Instant)>>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "status", content = "problems", rename_all = "snake_case")]
+enum Report {
+    /// Connecting to the node and initializing monitors
+    Starting,
+    Healthy,
+    Unhealthy(Vec<String>),
+}
+
+impl Health {
+    /// Marks node connection, chain checks and monitors as initialized
+    pub fn set_ready(&self, control: Arc<Control>) {
+        *self.ready.write().unwrap() = Some((control, Instant::now()));
+        info!("ready");
+    }
+
+    fn check(&self, cfg: &HealthConfig) -> Report {
+        let Some((control, ready_at)) = self.ready.read().unwrap().clone() else {
+            return Report::Starting;
+        };
+        let liveness = control.liveness();
+        let max_head_delay = control.timings().block_interval * cfg.max_missed_heads;
+        let now = Instant::now();
+
+        let mut problems = Vec::new();
+        if !liveness.subscribed {
+            problems.push("subscription to new heads is broken".to_string());
+        }
+        let since_head = now.duration_since(liveness.last_head_at.unwrap_or(ready_at));
+        if since_head > max_head_delay {
+            problems.push(format!("no new heads for {since_head:?}"));
+        }
+        if let Some(since) = liveness
+            .nonce_stuck_since
+            .filter(|since| now.duration_since(*since) > cfg.max_stuck_nonce)
+        {
+            problems.push(format!(
+                "skipping blocks due to stuck transactions for {:?}",
+                now.duration_since(since)
+            ));
+        }
+
+        if problems.is_empty() {
+            Report::Healthy
+        } else {
+            Report::Unhealthy(problems)
+        }
+    }
+}
+
+pub async fn bind(cfg: &HealthConfig) -> anyhow::Result<TcpListener> {
+    let addr = net::lookup_host(&cfg.listen)
+        .await
+        .with_context(|| format!("failed to lookup host: {}", cfg.listen))?
+        .next()
+        .ok_or_else(|| anyhow!("no addresses found for host: {}", cfg.listen))?;
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("failed to bind health endpoint '{addr}'"))?;
+    info!(%addr, "serving health endpoints");
+    Ok(listener)
+}
+
+/// Serves `/livez` and `/readyz` until cancelled. Both respond with
+/// 200 or 503 and JSON report: liveness fails only if the app is stuck,
+/// while readiness also fails until the app is initialized.
+pub async fn serve(
+    listener: TcpListener,
+    cfg: HealthConfig,
+    health: Arc<Health>,
+    cancel: CancellationToken,
+) {
+    let cfg = Arc::new(cfg);
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            conn = listener.accept() => match conn {
+                Ok((stream, _)) => {
+                    tokio::spawn(
+                        handle_connection(stream, cfg.clone(), health.clone())
+                            .inspect_err(|err| debug!(%err, "health connection failed")),
+                    );
+                }
+                Err(err) => error!(%err, "failed to accept health connection"),
+            },
+        }
+    }
+}
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST_LINE: u64 = 8192;
+
+async fn handle_connection(
+    stream: TcpStream,
+    cfg: Arc<HealthConfig>,
+    health: Arc<Health>,
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut request_line = String::new();
+    // clients which never finish the request line can't hold the connection
+    timeout(
+        REQUEST_TIMEOUT,
+        BufReader::new(reader.take(MAX_REQUEST_LINE)).read_line(&mut request_line),
+    )
+    .await
+    .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "request line timed out"))??;
+
+    let report = health.check(&cfg);
+    let status = match request_line.split_whitespace().nth(1) {
+        Some("/livez") if !matches!(report, Report::Unhealthy(_)) => "200 OK",
+        Some("/readyz") if matches!(report, Report::Healthy) => "200 OK",
+        Some("/livez" | "/readyz") => "503 Service Unavailable",
+        _ => "404 Not Found",
+    };
+
+    let body = serde_json::to_vec(&report)?;
+    let head = format!(
+        "HTTP/1.1 {status}\r\n\
+        Content-Type: application/json\r\n\
+        Content-Length: {}\r\n\
+        Connection: close\r\n\r\n",
+        body.len(),
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(&body).await?;
+    writer.shutdown().await
+}
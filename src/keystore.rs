@@ -0,0 +1,259 @@
+use std::{
+    fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use clap::{Args, Subcommand, ValueHint};
+use ethers::{
+    core::{k256::ecdsa::SigningKey, rand::thread_rng},
+    signers::{coins_bip39::English, MnemonicBuilder},
+    types::Address,
+    utils::{hex, secret_key_to_address},
+};
+
+const DEFAULT_DIR: &str = "./accounts";
+
+#[derive(Args)]
+pub struct PasswordArgs {
+    /// Password to decrypt keystore
+    #[arg(long, env = "SANDWITCH_KEYSTORE_PASSWORD")]
+    keystore_password: Option<String>,
+
+    /// Read password to decrypt keystore from the first line of the file
+    #[arg(
+        long,
+        env = "SANDWITCH_KEYSTORE_PASSWORD_FILE",
+        value_hint = ValueHint::FilePath,
+        value_name = "FILE",
+    )]
+    keystore_password_file: Option<PathBuf>,
+}
+
+impl PasswordArgs {
+    /// Password given with flags or read from file
+    pub fn given(&self) -> anyhow::Result<Option<String>> {
+        if let Some(password) = &self.keystore_password {
+            return Ok(Some(password.clone()));
+        }
+        let Some(path) = &self.keystore_password_file else {
+            return Ok(None);
+        };
+        let password = fs::read_to_string(path)
+            .with_context(|| format!("failed to read password file '{}'", path.display()))?;
+        Ok(Some(password.lines().next().unwrap_or_default().to_string()))
+    }
+
+    /// Given password or the one prompted if running interactively
+    pub fn given_or_prompt(&self) -> anyhow::Result<Option<String>> {
+        if let Some(password) = self.given()? {
+            return Ok(Some(password));
+        }
+        if !io::stdin().is_terminal() {
+            return Ok(None);
+        }
+        Ok(Some(rpassword::prompt_password("Keystore password: ")?))
+    }
+
+    fn get(&self) -> anyhow::Result<String> {
+        self.given_or_prompt()?
+            .ok_or_else(|| anyhow!("keystore password is required"))
+    }
+
+    /// Given password or the new one prompted twice
+    fn get_new(&self) -> anyhow::Result<String> {
+        match self.given()? {
+            Some(password) => Ok(password),
+            None => prompt_new_password(),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum KeystoreCommand {
+    /// Generate new random key
+    New {
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Import private key or mnemonic, both are read from prompt
+    Import {
+        /// Derive key from BIP-39 mnemonic instead of raw private key
+        #[arg(long)]
+        mnemonic: bool,
+        /// Index of the account derived from mnemonic
+        #[arg(long, default_value_t = 0, requires = "mnemonic")]
+        index: u32,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// List keystores in the directory along with their addresses
+    List {
+        #[arg(
+            value_hint = ValueHint::DirPath,
+            value_name = "DIR",
+            default_value = DEFAULT_DIR,
+        )]
+        dir: PathBuf,
+    },
+    /// Print address of the keystore
+    Address {
+        #[arg(value_hint = ValueHint::FilePath, value_name = "FILE")]
+        keystore: PathBuf,
+    },
+    /// Re-encrypt the keystore with new password
+    ChangePassword {
+        #[arg(value_hint = ValueHint::FilePath, value_name = "FILE")]
+        keystore: PathBuf,
+    },
+}
+
+#[derive(Args)]
+pub struct Output {
+    /// Directory to write keystore to
+    #[arg(
+        long,
+        value_hint = ValueHint::DirPath,
+        value_name = "DIR",
+        default_value = DEFAULT_DIR,
+    )]
+    dir: PathBuf,
+    /// Name of keystore file, random UUID if not set
+    #[arg(long)]
+    name: Option<String>,
+}
+
+impl Output {
+    fn create_dir(&self) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create directory '{}'", self.dir.display()))
+    }
+}
+
+impl KeystoreCommand {
+    pub fn run(self, password: &PasswordArgs) -> anyhow::Result<()> {
+        match self {
+            Self::New { output } => {
+                output.create_dir()?;
+                let (secret, name) = eth_keystore::new(
+                    &output.dir,
+                    &mut thread_rng(),
+                    password.get_new()?,
+                    output.name.as_deref(),
+                )?;
+                print_created(&output.dir.join(name), &secret)
+            }
+            Self::Import {
+                mnemonic,
+                index,
+                output,
+            } => {
+                let secret = if mnemonic {
+                    let phrase = rpassword::prompt_password("Mnemonic: ")?;
+                    let wallet = MnemonicBuilder::<English>::default()
+                        .phrase(phrase.trim())
+                        .index(index)?
+                        .build()?;
+                    wallet.signer().to_bytes().to_vec()
+                } else {
+                    let key = rpassword::prompt_password("Private key: ")?;
+                    hex::decode(key.trim().trim_start_matches("0x"))
+                        .context("invalid private key")?
+                };
+                // make sure key is valid before encrypting it
+                signing_key(&secret)?;
+
+                output.create_dir()?;
+                let name = eth_keystore::encrypt_key(
+                    &output.dir,
+                    &mut thread_rng(),
+                    &secret,
+                    password.get_new()?,
+                    output.name.as_deref(),
+                )?;
+                print_created(&output.dir.join(name), &secret)
+            }
+            Self::List { dir } => {
+                let mut entries = fs::read_dir(&dir)
+                    .with_context(|| format!("failed to read directory '{}'", dir.display()))?
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                entries.sort();
+                for path in entries.into_iter().filter(|path| path.is_file()) {
+                    match stored_address(&path) {
+                        Some(address) => println!("{address:?}\t{}", path.display()),
+                        None => println!("{:42}\t{}", "?", path.display()),
+                    }
+                }
+                Ok(())
+            }
+            Self::Address { keystore } => {
+                let address = match stored_address(&keystore) {
+                    Some(address) => address,
+                    None => secret_key_to_address(&signing_key(&decrypt(
+                        &keystore,
+                        &password.get()?,
+                    )?)?),
+                };
+                println!("{address:?}");
+                Ok(())
+            }
+            Self::ChangePassword { keystore } => {
+                let secret = decrypt(&keystore, &password.get()?)?;
+                let new_password = prompt_new_password()?;
+
+                let (dir, name) = keystore
+                    .parent()
+                    .zip(keystore.file_name().and_then(|name| name.to_str()))
+                    .ok_or_else(|| anyhow!("invalid keystore path '{}'", keystore.display()))?;
+                // old keystore is replaced only after the new one is written
+                let tmp = format!("{name}.tmp");
+                eth_keystore::encrypt_key(
+                    dir,
+                    &mut thread_rng(),
+                    &secret,
+                    new_password,
+                    Some(&tmp),
+                )?;
+                fs::rename(dir.join(tmp), &keystore)?;
+                println!("password of '{}' changed", keystore.display());
+                Ok(())
+            }
+        }
+    }
+}
+
+pub fn decrypt(path: impl AsRef<Path>, password: &str) -> anyhow::Result<Vec<u8>> {
+    let path = path.as_ref();
+    eth_keystore::decrypt_key(path, password)
+        .with_context(|| format!("failed to decrypt keystore '{}'", path.display()))
+}
+
+pub fn signing_key(secret: &[u8]) -> anyhow::Result<SigningKey> {
+    if secret.len() != 32 {
+        return Err(anyhow!("private key has to be 32 bytes long"));
+    }
+    SigningKey::from_bytes(secret.into()).context("invalid private key")
+}
+
+/// Address written to keystore file on creation, so it can be
+/// read without password
+fn stored_address(path: &Path) -> Option<Address> {
+    let keystore: serde_json::Value = serde_json::from_slice(&fs::read(path).ok()?).ok()?;
+    keystore.get("address")?.as_str()?.parse().ok()
+}
+
+fn prompt_new_password() -> anyhow::Result<String> {
+    let password = rpassword::prompt_password("New keystore password: ")?;
+    if password != rpassword::prompt_password("Repeat password: ")? {
+        return Err(anyhow!("passwords do not match"));
+    }
+    Ok(password)
+}
+
+fn print_created(path: &Path, secret: &[u8]) -> anyhow::Result<()> {
+    let address = secret_key_to_address(&signing_key(secret)?);
+    println!("{address:?}\t{}", path.display());
+    Ok(())
+}
@@ -0,0 +1,367 @@
+use std::{env, path::PathBuf};
+
+use anyhow::{anyhow, Context};
+use clap::Subcommand;
+use serde::de::DeserializeOwned;
+use tokio::fs;
+use url::Url;
+
+use crate::{
+    chains::{self, Chain},
+    AppConfig, Config, NetworkConfig,
+};
+
+/// Prefix of environment variables overriding config values,
+/// e.g. `SANDWITCH__ENGINE__BLOCK_INTERVAL_MS=3000`
+const ENV_PREFIX: &str = "SANDWITCH__";
+const ENV_SEPARATOR: &str = "__";
+
+const REDACTED: &str = "***";
+
+/// Config files merged in order, so values of the later ones take
+/// precedence, with environment overrides applied on top and
+/// `${ENV_VAR}` references in string values interpolated
+#[derive(Clone, Debug)]
+pub struct ConfigLayers {
+    files: Vec<PathBuf>,
+    /// Chain to apply the preset of if `chain` is not set
+    detected_chain: Option<Chain>,
+}
+
+impl ConfigLayers {
+    pub fn new(files: Vec<PathBuf>) -> anyhow::Result<Self> {
+        if files.is_empty() {
+            return Err(anyhow!("at least one config file is required"));
+        }
+        Ok(Self {
+            files,
+            detected_chain: None,
+        })
+    }
+
+    /// The most specific file, which is the one to write changes to
+    pub fn last(&self) -> &PathBuf {
+        self.files.last().expect("at least one config file")
+    }
+
+    /// Detects the chain of the node if `chain` is not set, so that
+    /// its preset is applied by this and further loads
+    pub async fn detect_chain(&mut self) -> anyhow::Result<()> {
+        let value = self.load().await?;
+        if value.get("chain").is_some() {
+            return Ok(());
+        }
+        let network: NetworkConfig = parse(
+            value
+                .get("network")
+                .cloned()
+                .ok_or_else(|| anyhow!("`network` is not set"))?,
+        )?;
+        self.detected_chain = chains::detect(&network).await?;
+        Ok(())
+    }
+
+    /// Effective config
+    pub async fn load(&self) -> anyhow::Result<toml::Value> {
+        let mut value = self.load_uninterpolated().await?;
+        interpolate(&mut value, &mut Vec::new(), &|var| {
+            env::var(var).map_err(|_| anyhow!("environment variable `{var}` is not set"))
+        })?;
+        Ok(value)
+    }
+
+    /// Effective config with interpolated references kept as is and
+    /// credentials stripped from URLs
+    pub async fn load_redacted(&self) -> anyhow::Result<toml::Value> {
+        let mut value = self.load_uninterpolated().await?;
+        // make sure referenced variables are set
+        interpolate(&mut value, &mut Vec::new(), &|var| {
+            env::var(var)
+                .map(|_| format!("${{{var}}}"))
+                .map_err(|_| anyhow!("environment variable `{var}` is not set"))
+        })?;
+        redact(&mut value);
+        Ok(value)
+    }
+
+    async fn load_uninterpolated(&self) -> anyhow::Result<toml::Value> {
+        let mut merged = toml::Value::Table(Default::default());
+        for path in &self.files {
+            let value: toml::Value = toml::from_str(
+                &fs::read_to_string(path)
+                    .await
+                    .with_context(|| format!("failed to read config file '{}'", path.display()))?,
+            )
+            .with_context(|| format!("failed to parse TOML config file '{}'", path.display()))?;
+            merge(&mut merged, value);
+        }
+        for (key, value) in env::vars_os() {
+            // names of ours are UTF-8, unrelated ones may be anything
+            let Some(key) = key.to_str() else {
+                continue;
+            };
+            let Some(path) = key.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let value = value
+                .into_string()
+                .map_err(|_| anyhow!("config override `{key}` is not valid UTF-8"))?;
+            let path: Vec<_> = path
+                .split(ENV_SEPARATOR)
+                .map(|key| key.to_lowercase())
+                .collect();
+            if path.iter().any(String::is_empty) {
+                return Err(anyhow!("invalid config override `{key}`"));
+            }
+            set(&mut merged, &path, parse_override(&value))
+                .with_context(|| format!("invalid config override `{key}`"))?;
+        }
+
+        if merged
+            .get("monitors")
+            .is_some_and(|monitors| monitors.get("pancake_swap").is_some())
+        {
+            return Err(anyhow!(
+                "`monitors.pancake_swap` is renamed to `monitors.uniswap_v2`"
+            ));
+        }
+
+        let chain = match merged.get("chain") {
+            Some(chain) => Some(
+                chain
+                    .clone()
+                    .try_into::<Chain>()
+                    .context("invalid config at `chain`")?,
+            ),
+            None => self.detected_chain,
+        };
+        if let (Some(chain), toml::Value::Table(table)) = (chain, &mut merged) {
+            chains::fill_defaults(table, chain.preset());
+        }
+        Ok(merged)
+    }
+}
+
+/// Deserializes the config value reporting the path of invalid one
+pub fn parse<T: DeserializeOwned>(value: toml::Value) -> anyhow::Result<T> {
+    serde_path_to_error::deserialize(value).map_err(|err| {
+        let path = err.path().to_string();
+        anyhow!("invalid config at `{path}`: {}", err.into_inner())
+    })
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Validate effective config
+    Check,
+    /// Print effective config with secrets redacted
+    Show,
+}
+
+impl ConfigCommand {
+    pub async fn run(self, mut layers: ConfigLayers) -> anyhow::Result<()> {
+        // presets of the node's chain are part of the effective config
+        let node_set =
+            layers.load().await?.get("network").is_some_and(|network| {
+                network.get("nodes").is_some() || network.get("node").is_some()
+            });
+        if node_set {
+            layers.detect_chain().await?;
+        }
+        match self {
+            Self::Check => {
+                let config: Config = parse(layers.load().await?)?;
+                if let Some(keystore) = &config.keystore {
+                    if !keystore.path.is_file() {
+                        return Err(anyhow!(
+                            "keystore '{}' does not exist",
+                            keystore.path.display()
+                        ));
+                    }
+                }
+                check_not_zero(&config.app)?;
+                println!("config is valid");
+                Ok(())
+            }
+            Self::Show => {
+                let value = layers.load_redacted().await?;
+                // make sure it is valid, though values are taken from the
+                // interpolated one since the redacted one can be invalid
+                parse::<Config>(layers.load().await?)?;
+                print!("{}", toml::to_string_pretty(&value)?);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Checks values which are valid to deserialize, but can't be zero
+fn check_not_zero(app: &AppConfig) -> anyhow::Result<()> {
+    let AppConfig {
+        network,
+        engine,
+        health,
+        ..
+    } = app;
+    if engine.multicall.is_zero() {
+        return Err(anyhow!(
+            "invalid config at `engine.multicall`: not set, deploy it with `deploy multicall`"
+        ));
+    }
+    let (retry, breaker) = (&network.retry, &engine.circuit_breaker);
+    let durations = [
+        ("network.node_timeout_ms", Some(network.node_timeout)),
+        (
+            "network.retry.initial_backoff_ms",
+            Some(retry.initial_backoff),
+        ),
+        ("network.retry.max_backoff_ms", Some(retry.max_backoff)),
+        ("network.batch.window_ms", network.batch.map(|b| b.window)),
+        ("engine.block_interval_ms", Some(engine.block_interval)),
+        (
+            "engine.tx_propagation_delay_ms",
+            Some(engine.tx_propagation_delay),
+        ),
+        (
+            "engine.pending_views_grace_ms",
+            Some(engine.pending_views_grace),
+        ),
+        (
+            "engine.circuit_breaker.loss_window_secs",
+            Some(breaker.loss_window),
+        ),
+        ("engine.circuit_breaker.cool_down_secs", breaker.cool_down),
+        (
+            "health.max_stuck_nonce_secs",
+            health.as_ref().map(|h| h.max_stuck_nonce),
+        ),
+    ];
+    match durations
+        .into_iter()
+        .find(|(_, duration)| duration.is_some_and(|duration| duration.is_zero()))
+    {
+        Some((path, _)) => Err(anyhow!("invalid config at `{path}`: must not be zero")),
+        None => Ok(()),
+    }
+}
+
+/// Merges tables recursively, other values are replaced
+fn merge(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn set(value: &mut toml::Value, path: &[String], new: toml::Value) -> anyhow::Result<()> {
+    let Some((key, rest)) = path.split_first() else {
+        *value = new;
+        return Ok(());
+    };
+    let table = value
+        .as_table_mut()
+        .ok_or_else(|| anyhow!("`{key}` is not in a table"))?;
+    let entry = table
+        .entry(key.clone())
+        .or_insert_with(|| toml::Value::Table(Default::default()));
+    set(entry, rest, new)
+}
+
+/// Parses override as TOML value falling back to string, so numbers,
+/// booleans and arrays can be set. Hex values are always strings since
+/// those are addresses rather than integers.
+fn parse_override(raw: &str) -> toml::Value {
+    if raw.starts_with("0x") {
+        return toml::Value::String(raw.to_string());
+    }
+    toml::from_str::<toml::Table>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// Replaces `${ENV_VAR}` references in string values with resolved ones
+fn interpolate(
+    value: &mut toml::Value,
+    path: &mut Vec<String>,
+    resolve: &impl Fn(&str) -> anyhow::Result<String>,
+) -> anyhow::Result<()> {
+    match value {
+        toml::Value::String(s) => {
+            *s = interpolate_str(s, resolve)
+                .with_context(|| format!("failed to interpolate `{}`", path.join(".")))?;
+        }
+        toml::Value::Array(values) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                path.push(i.to_string());
+                interpolate(value, path, resolve)?;
+                path.pop();
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                path.push(key.clone());
+                interpolate(value, path, resolve)?;
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn interpolate_str(
+    mut s: &str,
+    resolve: &impl Fn(&str) -> anyhow::Result<String>,
+) -> anyhow::Result<String> {
+    let mut result = String::with_capacity(s.len());
+    while let Some(start) = s.find("${") {
+        result.push_str(&s[..start]);
+        let rest = &s[start + 2..];
+        let end = rest
+            .find('}')
+            .ok_or_else(|| anyhow!("unterminated `${{`"))?;
+        result.push_str(&resolve(&rest[..end])?);
+        s = &rest[end + 1..];
+    }
+    result.push_str(s);
+    Ok(result)
+}
+
+/// Strips credentials, path and query from URLs, since node providers
+/// put API keys there
+fn redact(value: &mut toml::Value) {
+    match value {
+        toml::Value::String(s) => {
+            let Ok(mut url) = Url::parse(s) else {
+                return;
+            };
+            if !matches!(url.scheme(), "http" | "https" | "ws" | "wss") {
+                return;
+            }
+            let has_secrets = url.password().is_some()
+                || !url.username().is_empty()
+                || url.path() != "/"
+                || url.query().is_some();
+            if has_secrets {
+                let _ = url.set_username("");
+                let _ = url.set_password(None);
+                url.set_path(REDACTED);
+                url.set_query(None);
+                *s = url.to_string();
+            }
+        }
+        toml::Value::Array(values) => values.iter_mut().for_each(redact),
+        toml::Value::Table(table) => table.values_mut().for_each(redact),
+        _ => {}
+    }
+}
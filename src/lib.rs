@@ -1,6 +1,15 @@
-#![feature(result_flattening)]
+#![feature(result_flattening, is_terminal)]
 
+pub mod admin;
+pub mod chains;
+pub mod deploy;
+pub mod funds;
+pub mod health;
+pub mod keystore;
+pub mod layers;
+pub mod otlp;
 pub mod providers;
+pub mod reload;
 
 mod app;
 pub use app::*;
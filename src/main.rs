@@ -1,67 +1,264 @@
 #![feature(result_option_inspect, result_flattening)]
-use std::path::PathBuf;
+use std::{io, path::PathBuf, sync::Arc};
 
-use anyhow::Context;
-use clap::{Args, Parser, ValueHint};
+use anyhow::{anyhow, Context};
+use clap::{Args, Parser, Subcommand, ValueEnum, ValueHint};
+#[cfg(feature = "backtest")]
+use ethers::providers::Http;
+use ethers::providers::PubsubClient;
+use futures::future;
+use itertools::Itertools;
+use metrics::register_counter;
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder};
+use metrics_util::layers::FanoutBuilder;
 use opentelemetry::{sdk::Resource, KeyValue};
 use opentelemetry_otlp::WithExportConfig;
-use tokio::{fs, main, signal::ctrl_c};
+use opentelemetry_semantic_conventions::resource::{SERVICE_NAME, SERVICE_VERSION};
+use tokio::{
+    fs, net,
+    runtime::Builder,
+    signal::{
+        ctrl_c,
+        unix::{signal, SignalKind},
+    },
+};
 use tokio_util::sync::CancellationToken;
-use tracing::{info, Level, Subscriber};
+use tracing::{error, info, Level, Subscriber};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
 use tracing_opentelemetry::OpenTelemetryLayer;
-use tracing_subscriber::{filter::Targets, prelude::*, Layer, Registry};
+use tracing_subscriber::{
+    filter::{EnvFilter, LevelFilter, Targets},
+    fmt::MakeWriter,
+    prelude::*,
+    registry::LookupSpan,
+    Layer, Registry,
+};
+#[cfg(feature = "backtest")]
+use url::Url;
 
-use sandwitch::Config;
+#[cfg(feature = "backtest")]
+use sandwitch::providers::replay::ReplayProvider;
+use sandwitch::{
+    deploy::{AdminCommand, DeployCommand},
+    funds::FundsCommand,
+    health::{self, Health},
+    keystore::{KeystoreCommand, PasswordArgs},
+    layers::{self, ConfigCommand, ConfigLayers},
+    otlp::OtlpRecorder,
+    reload::Reloader,
+    App, Config,
+};
 
 #[derive(Parser)]
 #[command(version)]
 struct CliArgs {
+    /// Config files merged in order, the later ones override the former
     #[arg(
         short, long,
         value_parser,
         value_hint = ValueHint::FilePath,
         value_name = "FILE",
-        default_value_os_t = PathBuf::from("./sandwitch.toml"),
+        env = "SANDWITCH_CONFIG",
+        value_delimiter = ',',
+        default_value = "./sandwitch.toml",
     )]
-    config: PathBuf,
+    config: Vec<PathBuf>,
 
-    /// Password to decrypt keystore
-    #[arg(long, env = "SANDWITCH_KEYSTORE_PASSWORD")]
-    keystore_password: Option<String>,
+    #[command(flatten)]
+    password: PasswordArgs,
+
+    /// Serve Prometheus metrics at http://HOST:PORT/metrics,
+    /// overrides `metrics.listen`
+    #[arg(long, env = "SANDWITCH_METRICS_LISTEN", value_name = "HOST:PORT")]
+    metrics_listen: Option<String>,
 
     #[command(flatten)]
     logging: LoggingArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run against the node (default)
+    Run {
+        /// Record new heads and node responses to replay them with `backtest`
+        #[arg(long, value_hint = ValueHint::FilePath, value_name = "FILE")]
+        record: Option<PathBuf>,
+    },
+    /// Replay recording made with `run --record` through configured monitors
+    /// and report what would have been sent
+    #[cfg(feature = "backtest")]
+    Backtest {
+        #[arg(value_hint = ValueHint::FilePath, value_name = "RECORDING")]
+        recording: PathBuf,
+
+        /// Write JSON report to file instead of stdout
+        #[arg(long, value_hint = ValueHint::FilePath, value_name = "FILE")]
+        report: Option<PathBuf>,
+
+        /// Forward requests missing in the recording to archive node,
+        /// pinned to the replayed head
+        #[arg(long, value_hint = ValueHint::Url, value_name = "URL")]
+        archive: Option<Url>,
+    },
+    /// Deploy our contracts from the keystore account
+    Deploy {
+        #[command(subcommand)]
+        command: DeployCommand,
+    },
+    /// Manage our deployed contracts
+    Admin {
+        #[command(subcommand)]
+        command: AdminCommand,
+    },
+    /// Show and withdraw funds held by the multicall
+    Funds {
+        #[command(subcommand)]
+        command: FundsCommand,
+    },
+    /// Manage encrypted keystores
+    Keystore {
+        #[command(subcommand)]
+        command: KeystoreCommand,
+    },
+    /// Validate or print effective config
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
 }
 
 #[derive(Args)]
 struct LoggingArgs {
     #[arg(long, value_name = "HOST:PORT")]
-    /// Endpoint for OTLP metrics
+    /// Endpoint for OTLP traces
     otlp_endpoint: Option<String>,
+    /// Also export metrics to `--otlp-endpoint`, overrides `metrics.otlp_endpoint`
+    #[arg(long, requires = "otlp_endpoint")]
+    otlp_metrics: bool,
     #[arg(
         short, long,
         action = clap::ArgAction::Count,
     )]
-    /// Increase verbosity (error (deafult) -> warn -> info -> debug -> trace)
+    /// Increase verbosity of sandwitch crates
+    /// (error (deafult) -> warn -> info -> debug -> trace)
     verbose: u8,
+    /// Per-target filter directives, e.g. `sandwitch=info,ethers=warn`,
+    /// overrides `--verbose`
+    #[arg(long, env = "RUST_LOG", value_name = "DIRECTIVES")]
+    log_filter: Option<String>,
+    #[arg(long, value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+    /// Also write logs to files in this directory
+    #[arg(long, value_hint = ValueHint::DirPath, value_name = "DIR")]
+    log_dir: Option<PathBuf>,
+    /// How often to start a new log file in `--log-dir`
+    #[arg(long, value_enum, default_value_t = LogRotation::Daily)]
+    log_rotation: LogRotation,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogFormat {
+    Pretty,
+    Compact,
+    /// One JSON object per line with fields of current span and its parents
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogRotation {
+    Minutely,
+    Hourly,
+    Daily,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Minutely => Self::MINUTELY,
+            LogRotation::Hourly => Self::HOURLY,
+            LogRotation::Daily => Self::DAILY,
+            LogRotation::Never => Self::NEVER,
+        }
+    }
 }
 
+/// Crates of the workspace, which `--verbose` applies to
+const LOG_TARGETS: &[&str] = &[
+    env!("CARGO_PKG_NAME"),
+    "sandwitch_engine",
+    "sandwitch_contracts",
+    "sandwitch_monitor_erc20",
+];
+
 impl LoggingArgs {
-    pub fn make_subscriber(self) -> anyhow::Result<impl Subscriber> {
-        Ok(Registry::default()
+    fn filter(&self) -> anyhow::Result<EnvFilter> {
+        let directives = match &self.log_filter {
+            Some(directives) => directives.clone(),
+            None => {
+                let level = [
+                    Level::ERROR,
+                    Level::WARN,
+                    Level::INFO,
+                    Level::DEBUG,
+                    Level::TRACE,
+                ][(self.verbose.min(4)) as usize];
+                LOG_TARGETS
+                    .iter()
+                    .map(|target| format!("{target}={level}"))
+                    .join(",")
+            }
+        };
+        EnvFilter::builder()
+            .with_default_directive(LevelFilter::ERROR.into())
+            .parse(&directives)
+            .with_context(|| format!("invalid log filter '{directives}'"))
+    }
+
+    fn fmt_layer<S, W>(&self, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+    {
+        let layer = tracing_subscriber::fmt::layer()
+            .with_writer(writer)
+            .with_ansi(ansi);
+        match self.log_format {
+            LogFormat::Pretty => layer.pretty().with_source_location(false).boxed(),
+            LogFormat::Compact => layer.compact().boxed(),
+            LogFormat::Json => layer
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
+                .boxed(),
+        }
+    }
+
+    /// Returned guard flushes file logs on drop
+    pub fn make_subscriber(self) -> anyhow::Result<(impl Subscriber, Option<WorkerGuard>)> {
+        let (file, guard) = match &self.log_dir {
+            Some(dir) => {
+                let (file, guard) = tracing_appender::non_blocking(RollingFileAppender::new(
+                    self.log_rotation.into(),
+                    dir,
+                    concat!(env!("CARGO_PKG_NAME"), ".log"),
+                ));
+                (Some(self.fmt_layer(file, false)), Some(guard))
+            }
+            None => (None, None),
+        };
+
+        let subscriber = Registry::default()
             .with(
-                tracing_subscriber::fmt::layer()
-                    .map_event_format(|f| f.pretty().with_source_location(false))
-                    .with_filter(Targets::from_iter([(
-                        env!("CARGO_PKG_NAME"),
-                        [
-                            Level::ERROR,
-                            Level::WARN,
-                            Level::INFO,
-                            Level::DEBUG,
-                            Level::TRACE,
-                        ][(self.verbose.min(4)) as usize],
-                    )])),
+                self.fmt_layer(io::stderr, true)
+                    .and_then(file)
+                    .with_filter(self.filter()?),
             )
             .with(if let Some(endpoint) = self.otlp_endpoint {
                 Some(
@@ -74,78 +271,268 @@ impl LoggingArgs {
                                     .with_endpoint(endpoint),
                             )
                             .with_trace_config(opentelemetry::sdk::trace::config().with_resource(
-                                Resource::new([KeyValue::new(
-                                    opentelemetry_semantic_conventions::resource::SERVICE_NAME,
-                                    env!("CARGO_PKG_NAME"),
-                                )]),
+                                Resource::new([KeyValue::new(SERVICE_NAME, env!("CARGO_PKG_NAME"))]),
                             ))
                             .install_batch(opentelemetry::runtime::Tokio)?,
                     )
                     .with_filter(Targets::from_iter([
                         (env!("CARGO_PKG_NAME"), Level::TRACE),
+                        ("sandwitch_engine", Level::TRACE),
                         ("ethers_providers::rpc::provider", Level::TRACE),
                     ])),
                 )
             } else {
                 None
-            }))
+            });
+        Ok((subscriber, guard))
     }
 }
 
-#[main]
-async fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<()> {
     let args = CliArgs::parse();
 
-    let config: Config = toml::from_str(
-        fs::read_to_string(&args.config)
-            .await
-            .with_context(|| format!("failed to read config file '{}'", args.config.display()))?
-            .as_str(),
+    let mut runtime = match args.command {
+        // replay recorded blocks as fast as they are processed, not as they were received
+        #[cfg(feature = "backtest")]
+        Some(Command::Backtest { .. }) => {
+            let mut runtime = Builder::new_current_thread();
+            runtime.start_paused(true);
+            runtime
+        }
+        _ => Builder::new_multi_thread(),
+    };
+    runtime.enable_all().build()?.block_on(run(args))
+}
+
+async fn run(args: CliArgs) -> anyhow::Result<()> {
+    let command = match args.command {
+        // does not need config
+        Some(Command::Keystore { command }) => return command.run(&args.password),
+        Some(Command::Config { command }) => {
+            return command.run(ConfigLayers::new(args.config)?).await
+        }
+        command => command.unwrap_or(Command::Run { record: None }),
+    };
+
+    let (subscriber, _log_guard) = args.logging.make_subscriber()?;
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    let mut layers = ConfigLayers::new(args.config)?;
+    // recordings are replayed without node
+    #[cfg(feature = "backtest")]
+    let detect_chain = !matches!(command, Command::Backtest { .. });
+    #[cfg(not(feature = "backtest"))]
+    let detect_chain = true;
+    if detect_chain {
+        layers.detect_chain().await?;
+    }
+    let raw_config = layers.load().await?;
+    let mut config: Config = layers::parse(raw_config.clone())?;
+
+    // resource attributes are known only after initialization
+    let otlp_metrics = args
+        .logging
+        .otlp_endpoint
+        .clone()
+        .filter(|_| args.logging.otlp_metrics)
+        .or_else(|| config.metrics.otlp_endpoint.clone())
+        .map(|endpoint| (endpoint, OtlpRecorder::default()));
+    install_metrics_recorder(
+        args.metrics_listen
+            .as_deref()
+            .or(config.metrics.listen.as_deref()),
+        otlp_metrics.as_ref().map(|(_, recorder)| recorder.clone()),
     )
-    .with_context(|| {
-        format!(
-            "failed to parse TOML config file '{}'",
-            args.config.display()
-        )
-    })?;
+    .await?;
 
-    tracing::subscriber::set_global_default(args.logging.make_subscriber()?)?;
+    match command {
+        Command::Run { record } => {
+            let cancel = make_ctrl_c_cancel();
+            // serve health before initialization to report that we are starting
+            let health = match config.app.health.take() {
+                Some(cfg) => {
+                    let listener = health::bind(&cfg).await?;
+                    let health = Arc::new(Health::default());
+                    tokio::spawn(health::serve(
+                        listener,
+                        cfg,
+                        health.clone(),
+                        cancel.child_token(),
+                    ));
+                    Some(health)
+                }
+                None => None,
+            };
 
-    let app = config.init(args.keystore_password).await?;
+            let password = keystore_password(&config, &args.password)?;
+            let app = config.init(password, record.as_deref()).await?;
+            if let Some(health) = health {
+                health.set_ready(app.control());
+            }
+            let otlp_metrics = otlp_metrics
+                .map(|(endpoint, recorder)| {
+                    recorder.start(
+                        endpoint,
+                        Resource::new([
+                            KeyValue::new(SERVICE_NAME, env!("CARGO_PKG_NAME")),
+                            KeyValue::new(SERVICE_VERSION, env!("CARGO_PKG_VERSION")),
+                            KeyValue::new("chain.id", app.chain_id() as i64),
+                            KeyValue::new("account", format!("{:?}", app.account())),
+                        ]),
+                    )
+                })
+                .transpose()?;
 
-    let cancel = make_ctrl_c_cancel();
+            let reloader = app.reloader(layers, raw_config);
+            let r = run_app(app, cancel, Some(reloader)).await;
+            if let Some(controller) = otlp_metrics {
+                // export what was collected since the last period
+                controller.stop(&opentelemetry::Context::current())?;
+            }
+            r
+        }
+        #[cfg(feature = "backtest")]
+        Command::Backtest {
+            recording,
+            report: report_path,
+            archive,
+        } => {
+            let replay = ReplayProvider::load(&recording, archive.map(Http::new))?;
+            // missing responses should not stop the whole replay
+            config.app.engine.skip_failed_blocks = true;
+            let password = keystore_password(&config, &args.password)?;
+            let app = config.init_with_client(replay.clone(), password).await?;
 
-    app.run(cancel.child_token()).await?;
+            let cancel = make_ctrl_c_cancel();
+            tokio::spawn({
+                let (cancel, finished) = (cancel.clone(), replay.finished());
+                async move {
+                    finished.cancelled().await;
+                    cancel.cancel();
+                }
+            });
+            run_app(app, cancel, None).await?;
+
+            let report = serde_json::to_string_pretty(&replay.report())?;
+            match report_path {
+                Some(path) => fs::write(&path, report)
+                    .await
+                    .with_context(|| format!("failed to write report '{}'", path.display()))?,
+                None => println!("{report}"),
+            }
+            Ok(())
+        }
+        Command::Deploy { command } => {
+            let password = keystore_password(&config, &args.password)?;
+            command.run(&config, layers.last(), password).await
+        }
+        Command::Admin { command } => {
+            let password = keystore_password(&config, &args.password)?;
+            command.run(&config, password).await
+        }
+        Command::Funds { command } => {
+            let password = keystore_password(&config, &args.password)?;
+            command.run(&config, password).await
+        }
+        Command::Keystore { .. } | Command::Config { .. } => {
+            unreachable!("handled before reading config")
+        }
+    }
+}
+
+async fn run_app<P>(
+    app: App<P>,
+    cancel: CancellationToken,
+    reloader: Option<Reloader<P>>,
+) -> anyhow::Result<()>
+where
+    P: PubsubClient + 'static,
+{
+    let breakers = app.circuit_breakers();
+    let mut reset_requests = signal(SignalKind::user_defined1())
+        .context("failed to set SIGUSR1 handler")?;
+    tokio::spawn(async move {
+        while reset_requests.recv().await.is_some() {
+            info!("circuit breakers reset requested");
+            breakers.reset_all();
+        }
+    });
+
+    let mut hangups = signal(SignalKind::hangup()).context("failed to set SIGHUP handler")?;
+    let reload = async move {
+        let Some(mut reloader) = reloader else {
+            return future::pending().await;
+        };
+        while hangups.recv().await.is_some() {
+            info!("config reload requested");
+            if let Err(err) = reloader.reload().await {
+                error!("failed to reload config: {err:#}");
+            }
+        }
+        future::pending().await
+    };
+
+    // monitors are rebuilt on reload, which is not Send, so reload is not spawned
+    tokio::select! {
+        r = app.run(cancel.child_token()) => r?,
+        () = reload => {},
+    }
 
     info!("shutdown");
     Ok(())
 }
 
-// async fn install_prometheus_metrics_recoder_and_exporter(
-//     host: impl AsRef<str>,
-//     port: u16,
-// ) -> anyhow::Result<()> {
-//     let host = host.as_ref();
-//     PrometheusBuilder::new()
-//         .with_http_listener(
-//             net::lookup_host((host, port))
-//                 .await
-//                 .with_context(|| format!("failed to lookup host: {host}:{port}",))?
-//                 .next()
-//                 .unwrap(),
-//         )
-//         .set_buckets_for_metric(
-//             Matcher::Suffix("_duration".to_string()),
-//             &[
-//                 0.01, 0.05, 0.075, 0.1, 0.125, 0.15, 0.175, 0.2, 0.225, 0.25, 0.275, 0.3, 0.35,
-//                 0.4, 0.5, 0.6, 0.7, 0.8, 1.,
-//             ],
-//         )
-//         .unwrap()
-//         .install()?;
-//     register_counter!("sandwitch_build_info", "version" => env!("CARGO_PKG_VERSION")).absolute(1);
-//     Ok(())
-// }
+/// Installs recorder exporting metrics to enabled destinations
+async fn install_metrics_recorder(
+    prometheus_listen: Option<&str>,
+    otlp: Option<OtlpRecorder>,
+) -> anyhow::Result<()> {
+    if prometheus_listen.is_none() && otlp.is_none() {
+        return Ok(());
+    }
+    let mut fanout = FanoutBuilder::default();
+
+    if let Some(addr) = prometheus_listen {
+        let (recorder, exporter) = PrometheusBuilder::new()
+            .with_http_listener(
+                net::lookup_host(addr)
+                    .await
+                    .with_context(|| format!("failed to lookup host: {addr}"))?
+                    .next()
+                    .ok_or_else(|| anyhow!("no addresses found for host: {addr}"))?,
+            )
+            .set_buckets_for_metric(
+                Matcher::Suffix("_duration".to_string()),
+                &[
+                    0.01, 0.05, 0.075, 0.1, 0.125, 0.15, 0.175, 0.2, 0.225, 0.25, 0.275, 0.3,
+                    0.35, 0.4, 0.5, 0.6, 0.7, 0.8, 1., 1.5, 2., 3., 5.,
+                ],
+            )?
+            .build()?;
+        tokio::spawn(async move {
+            if let Err(err) = exporter.await {
+                error!(%err, "Prometheus exporter failed");
+            }
+        });
+        fanout = fanout.add_recorder(recorder);
+        info!(%addr, "serving Prometheus metrics");
+    }
+    if let Some(otlp) = otlp {
+        fanout = fanout.add_recorder(otlp);
+    }
+
+    metrics::set_boxed_recorder(Box::new(fanout.build()))?;
+    register_counter!("sandwitch_build_info", "version" => env!("CARGO_PKG_VERSION")).absolute(1);
+    Ok(())
+}
+
+/// Password is prompted only if keystore is set
+fn keystore_password(config: &Config, password: &PasswordArgs) -> anyhow::Result<Option<String>> {
+    if config.keystore.is_none() {
+        return Ok(None);
+    }
+    password.given_or_prompt()
+}
 
 fn make_ctrl_c_cancel() -> CancellationToken {
     let cancel = CancellationToken::new();
@@ -0,0 +1,203 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+};
+
+use metrics::{
+    Counter, Gauge, Histogram, HistogramFn, Key, KeyName, Recorder, SharedString, Unit,
+};
+use opentelemetry::{
+    metrics::{self as otel, Meter, MeterProvider},
+    sdk::{
+        export::metrics::aggregation::cumulative_temporality_selector,
+        metrics::{controllers::BasicController, selectors},
+        Resource,
+    },
+    Context, KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+
+/// Buckets of histograms, in seconds for `_duration` ones
+const BUCKETS: &[f64] = &[
+    0.01, 0.05, 0.075, 0.1, 0.125, 0.15, 0.175, 0.2, 0.225, 0.25, 0.275, 0.3, 0.35, 0.4, 0.5,
+    0.6, 0.7, 0.8, 1., 1.5, 2., 3., 5.,
+];
+
+/// [`Recorder`] exporting metrics via OTLP. Exporting starts only with
+/// [`OtlpRecorder::start()`], since resource attributes are known after
+/// initialization. Counters and gauges keep their values until then,
+/// while histograms recorded before are dropped.
+#[derive(Clone, Default)]
+pub struct OtlpRecorder(Arc<Inner>);
+
+#[derive(Default)]
+struct Inner {
+    meter: RwLock<Option<Meter>>,
+    counters: Mutex<HashMap<Key, Arc<AtomicU64>>>,
+    /// Values are [`f64`] bits
+    gauges: Mutex<HashMap<Key, Arc<AtomicU64>>>,
+    instruments: Mutex<Instruments>,
+}
+
+/// OpenTelemetry instruments by metric name
+#[derive(Default)]
+struct Instruments {
+    counters: HashMap<String, otel::ObservableCounter<u64>>,
+    gauges: HashMap<String, otel::ObservableGauge<f64>>,
+    histograms: HashMap<String, otel::Histogram<f64>>,
+}
+
+impl Inner {
+    /// Creates observable instruments for registered counters and gauges
+    /// which do not have them yet
+    fn init_observable(&self, meter: &Meter) {
+        let mut instruments = self.instruments.lock().unwrap();
+        for key in self.counters.lock().unwrap().keys() {
+            instruments
+                .counters
+                .entry(key.name().to_string())
+                .or_insert_with(|| meter.u64_observable_counter(key.name().to_string()).init());
+        }
+        for key in self.gauges.lock().unwrap().keys() {
+            instruments
+                .gauges
+                .entry(key.name().to_string())
+                .or_insert_with(|| meter.f64_observable_gauge(key.name().to_string()).init());
+        }
+    }
+
+    fn observe(&self, cx: &Context) {
+        let instruments = self.instruments.lock().unwrap();
+        for (key, value) in self.counters.lock().unwrap().iter() {
+            if let Some(counter) = instruments.counters.get(key.name()) {
+                counter.observe(cx, value.load(Ordering::Relaxed), &attributes(key));
+            }
+        }
+        for (key, value) in self.gauges.lock().unwrap().iter() {
+            if let Some(gauge) = instruments.gauges.get(key.name()) {
+                gauge.observe(
+                    cx,
+                    f64::from_bits(value.load(Ordering::Relaxed)),
+                    &attributes(key),
+                );
+            }
+        }
+    }
+
+    /// Creates instruments for metrics registered after start
+    fn registered(&self) {
+        if let Some(meter) = &*self.meter.read().unwrap() {
+            self.init_observable(meter);
+        }
+    }
+}
+
+impl OtlpRecorder {
+    /// Starts periodic export to `endpoint` with given resource attributes
+    pub fn start(
+        &self,
+        endpoint: impl Into<String>,
+        resource: Resource,
+    ) -> anyhow::Result<BasicController> {
+        let controller = opentelemetry_otlp::new_pipeline()
+            .metrics(
+                selectors::simple::histogram(BUCKETS),
+                cumulative_temporality_selector(),
+                opentelemetry::runtime::Tokio,
+            )
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(endpoint),
+            )
+            .with_resource(resource)
+            .build()?;
+        let meter = controller.versioned_meter(
+            env!("CARGO_PKG_NAME"),
+            Some(env!("CARGO_PKG_VERSION")),
+            None,
+        );
+
+        // counters and gauges are observed on export
+        self.0.init_observable(&meter);
+        let inner = self.0.clone();
+        meter.register_callback(move |cx| inner.observe(cx))?;
+
+        *self.0.meter.write().unwrap() = Some(meter);
+        Ok(controller)
+    }
+}
+
+impl Recorder for OtlpRecorder {
+    fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_histogram(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {
+    }
+
+    fn register_counter(&self, key: &Key) -> Counter {
+        let value = self
+            .0
+            .counters
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        self.0.registered();
+        Counter::from_arc(value)
+    }
+
+    fn register_gauge(&self, key: &Key) -> Gauge {
+        let value = self
+            .0
+            .gauges
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        self.0.registered();
+        Gauge::from_arc(value)
+    }
+
+    fn register_histogram(&self, key: &Key) -> Histogram {
+        Histogram::from_arc(Arc::new(OtlpHistogram {
+            key: key.clone(),
+            attributes: attributes(key),
+            inner: self.0.clone(),
+        }))
+    }
+}
+
+struct OtlpHistogram {
+    key: Key,
+    attributes: Vec<KeyValue>,
+    inner: Arc<Inner>,
+}
+
+impl HistogramFn for OtlpHistogram {
+    fn record(&self, value: f64) {
+        let Some(meter) = &*self.inner.meter.read().unwrap() else {
+            return;
+        };
+        self.inner
+            .instruments
+            .lock()
+            .unwrap()
+            .histograms
+            .entry(self.key.name().to_string())
+            .or_insert_with(|| meter.f64_histogram(self.key.name().to_string()).init())
+            .record(&Context::current(), value, &self.attributes);
+    }
+}
+
+fn attributes(key: &Key) -> Vec<KeyValue> {
+    key.labels()
+        .map(|label| KeyValue::new(label.key().to_string(), label.value().to_string()))
+        .collect()
+}
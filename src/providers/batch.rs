@@ -0,0 +1,303 @@
+use core::{fmt::Debug, future::Future, marker, pin::Pin, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use ethers::providers::{JsonRpcClient, JsonRpcError, ProviderError, RpcError};
+use futures::FutureExt;
+use metrics::{register_histogram, Histogram};
+use reqwest::{header, Client};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::value::{to_raw_value, RawValue};
+use serde_with::{serde_as, DurationMilliSeconds};
+use thiserror::Error as ThisError;
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::{timeout_at, Instant},
+};
+use tracing::debug;
+use url::Url;
+
+#[serde_as]
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct BatchConfig {
+    /// Time to wait for more requests after the first one of a batch
+    #[serde(rename = "window_ms")]
+    #[serde(default = "BatchConfig::default_window")]
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub window: Duration,
+
+    /// Batch is sent at once when it reaches this size
+    #[serde(default = "BatchConfig::default_max_size")]
+    pub max_size: usize,
+}
+
+impl BatchConfig {
+    fn default_window() -> Duration {
+        Duration::from_millis(2)
+    }
+
+    fn default_max_size() -> usize {
+        50
+    }
+}
+
+/// HTTP transport coalescing requests made within a short window into
+/// a single JSON-RPC batch, so that bursts of independent requests cost
+/// one round trip over the same connection
+#[derive(Debug)]
+pub struct BatchingProvider {
+    next_id: AtomicU64,
+    requests: mpsc::UnboundedSender<Queued>,
+}
+
+struct Queued {
+    id: u64,
+    request: Box<RawValue>,
+    response: oneshot::Sender<Result<Box<RawValue>, BatchingProviderError>>,
+}
+
+#[derive(Serialize)]
+struct Request<'a, T> {
+    jsonrpc: &'static str,
+    id: u64,
+    method: &'a str,
+    params: T,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    id: Option<u64>,
+    result: Option<Box<RawValue>>,
+    error: Option<JsonRpcError>,
+}
+
+impl Response {
+    fn into_result(self) -> Result<Box<RawValue>, BatchingProviderError> {
+        match (self.result, self.error) {
+            (_, Some(err)) => Err(BatchingProviderError::JsonRpc(err)),
+            (Some(result), None) => Ok(result),
+            // `null` results are deserialized as none
+            (None, None) => Ok(to_raw_value(&()).expect("null is always serializable")),
+        }
+    }
+}
+
+impl BatchingProvider {
+    /// Starts batching requests to the node at `url`
+    pub fn new(url: Url, cfg: BatchConfig) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(
+            Batcher {
+                client: Client::new(),
+                url,
+                cfg,
+                requests: rx,
+                sizes: register_histogram!("sandwitch_rpc_batch_size"),
+            }
+            .run(),
+        );
+        Self {
+            next_id: AtomicU64::new(1),
+            requests: tx,
+        }
+    }
+}
+
+impl JsonRpcClient for BatchingProvider {
+    type Error = BatchingProviderError;
+
+    fn request<'life0, 'life1, 'async_trait, T, R>(
+        &'life0 self,
+        method: &'life1 str,
+        params: T,
+    ) -> Pin<Box<dyn Future<Output = Result<R, Self::Error>> + marker::Send + 'async_trait>>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+        T: 'async_trait,
+        R: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+    {
+        async move {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let request = to_raw_value(&Request {
+                jsonrpc: "2.0",
+                id,
+                method,
+                params,
+            })?;
+            let (tx, rx) = oneshot::channel();
+            self.requests
+                .send(Queued {
+                    id,
+                    request,
+                    response: tx,
+                })
+                .map_err(|_| BatchingProviderError::Closed)?;
+            let response = rx.await.map_err(|_| BatchingProviderError::Closed)??;
+            Ok(serde_json::from_str(response.get())?)
+        }
+        .boxed()
+    }
+}
+
+struct Batcher {
+    client: Client,
+    url: Url,
+    cfg: BatchConfig,
+    requests: mpsc::UnboundedReceiver<Queued>,
+    sizes: Histogram,
+}
+
+impl Batcher {
+    /// Collects batches until the provider is dropped
+    async fn run(mut self) {
+        while let Some(first) = self.requests.recv().await {
+            let window_ends_at = Instant::now() + self.cfg.window;
+            let mut batch = vec![first];
+            while batch.len() < self.cfg.max_size {
+                match timeout_at(window_ends_at, self.requests.recv()).await {
+                    Ok(Some(queued)) => batch.push(queued),
+                    Ok(None) | Err(_) => break,
+                }
+            }
+            self.sizes.record(batch.len() as f64);
+            tokio::spawn(send(self.client.clone(), self.url.clone(), batch));
+        }
+    }
+}
+
+/// Sends the batch resolving each of requests with its response
+async fn send(client: Client, url: Url, batch: Vec<Queued>) {
+    let body = match &batch[..] {
+        // some nodes do not accept batches, so single requests are sent as is
+        [single] => single.request.get().to_string(),
+        batch => format!(
+            "[{}]",
+            batch
+                .iter()
+                .map(|queued| queued.request.get())
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+    };
+    let received = async {
+        client
+            .post(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await
+    }
+    .await;
+    let received = match received {
+        Ok(received) => received,
+        Err(err) => {
+            let err = Arc::new(err);
+            for queued in batch {
+                let _ = queued
+                    .response
+                    .send(Err(BatchingProviderError::Http(err.clone())));
+            }
+            return;
+        }
+    };
+
+    let responses = match serde_json::from_slice::<Vec<Response>>(&received) {
+        Ok(responses) => responses,
+        // single requests are responded with a single object, as well as
+        // rejected batches with an error without id
+        Err(_) => match serde_json::from_slice::<Response>(&received) {
+            Ok(response) => vec![response],
+            Err(err) => {
+                let err = Arc::new(err);
+                for queued in batch {
+                    let _ = queued
+                        .response
+                        .send(Err(BatchingProviderError::Batch(err.clone())));
+                }
+                return;
+            }
+        },
+    };
+    let mut rejected = None;
+    let mut by_id = HashMap::with_capacity(responses.len());
+    for response in responses {
+        match response.id {
+            Some(id) => {
+                by_id.insert(id, response);
+            }
+            None => rejected = response.error,
+        }
+    }
+    debug!(
+        requests = batch.len(),
+        responses = by_id.len(),
+        "batch received"
+    );
+    for queued in batch {
+        let result = match (by_id.remove(&queued.id), &rejected) {
+            (Some(response), _) => response.into_result(),
+            (None, Some(err)) => Err(BatchingProviderError::JsonRpc(err.clone())),
+            (None, None) => Err(BatchingProviderError::MissingResponse(queued.id)),
+        };
+        let _ = queued.response.send(result);
+    }
+}
+
+#[derive(ThisError, Debug)]
+pub enum BatchingProviderError {
+    #[error("batch request failed: {0}")]
+    Http(Arc<reqwest::Error>),
+
+    #[error("invalid batch response: {0}")]
+    Batch(Arc<serde_json::Error>),
+
+    #[error("no response to request {0} in batch")]
+    MissingResponse(u64),
+
+    #[error(transparent)]
+    JsonRpc(JsonRpcError),
+
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+
+    #[error("batching stopped")]
+    Closed,
+}
+
+impl RpcError for BatchingProviderError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::JsonRpc(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Serde(err) => Some(err),
+            Self::Batch(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<BatchingProviderError> for ProviderError {
+    fn from(e: BatchingProviderError) -> Self {
+        match e {
+            BatchingProviderError::Serde(e) => e.into(),
+            e => ProviderError::JsonRpcClientError(Box::new(e) as Box<dyn RpcError + Send + Sync>),
+        }
+    }
+}
@@ -0,0 +1,573 @@
+use core::{fmt::Debug, future::Future, marker, pin::Pin, time::Duration};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{JsonRpcClient, JsonRpcError, ProviderError, PubsubClient, RpcError},
+    types::{Block, Transaction, U256},
+};
+use futures::{
+    stream::{self, BoxStream, SelectAll, StreamExt},
+    FutureExt,
+};
+use metrics::{register_counter, register_gauge, register_histogram, Counter, Gauge, Histogram};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, value::RawValue, Value};
+use thiserror::Error as ThisError;
+use tokio::{
+    sync::mpsc,
+    time::{interval, timeout, Instant, MissedTickBehavior},
+};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_util::sync::{CancellationToken, DropGuard};
+use tracing::{debug, info, warn};
+
+use sandwitch_engine::pending::PendingView;
+
+/// Node is considered unhealthy after this many failures in a row
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+/// Unhealthy nodes are tried only if healthy ones fail within this period
+const COOL_DOWN: Duration = Duration::from_secs(10);
+/// Number of nodes to subscribe to, so that there is a backup one
+const SUBSCRIPTIONS_PER_KIND: usize = 2;
+/// Interval of attempts to replace failed subscriptions
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(5);
+/// Number of recent notifications remembered to drop duplicates
+/// coming from different nodes
+const SEEN_NOTIFICATIONS: usize = 64;
+
+/// Routes requests to the healthiest of prioritized nodes failing over
+/// to the next ones on transport errors and timeouts. Subscriptions are
+/// made to several nodes at once with duplicate notifications dropped,
+/// so that a backup one keeps delivering heads if the primary fails.
+#[derive(Clone)]
+pub struct FailoverProvider<P>(Arc<Nodes<P>>);
+
+struct Nodes<P> {
+    /// In order of priority
+    nodes: Vec<Node<P>>,
+    timeout: Duration,
+    next_id: AtomicU64,
+    subscriptions: Mutex<HashMap<U256, Subscription>>,
+}
+
+struct Node<P> {
+    name: String,
+    client: P,
+    health: Mutex<NodeHealth>,
+    metrics: NodeMetrics,
+}
+
+#[derive(Default)]
+struct NodeHealth {
+    consecutive_failures: u32,
+    unhealthy_until: Option<Instant>,
+}
+
+struct Subscription {
+    /// Taken on [`PubsubClient::subscribe()`]
+    notifications: Option<mpsc::UnboundedReceiver<Box<RawValue>>>,
+    _stop: DropGuard,
+}
+
+impl<P> FailoverProvider<P> {
+    /// Nodes are given by name in order of priority, each request
+    /// to a node is limited by `timeout`
+    pub fn new(nodes: impl IntoIterator<Item = (String, P)>, timeout: Duration) -> Self {
+        let nodes: Vec<_> = nodes
+            .into_iter()
+            .map(|(name, client)| Node {
+                metrics: NodeMetrics::new(&name),
+                name,
+                client,
+                health: Default::default(),
+            })
+            .collect();
+        assert!(!nodes.is_empty(), "at least one node is required");
+        Self(Arc::new(Nodes {
+            nodes,
+            timeout,
+            next_id: AtomicU64::new(1),
+            subscriptions: Default::default(),
+        }))
+    }
+}
+
+impl<P> FailoverProvider<P>
+where
+    P: PubsubClient + 'static,
+    P::Error: 'static,
+{
+    /// Pending blocks of each node to be merged by engine
+    pub fn pending_views(&self) -> Vec<Arc<dyn PendingView>> {
+        (0..self.0.nodes.len())
+            .map(|node| {
+                Arc::new(NodeView {
+                    nodes: self.0.clone(),
+                    node,
+                }) as Arc<dyn PendingView>
+            })
+            .collect()
+    }
+}
+
+/// Pending block of a single node
+pub struct NodeView<P> {
+    nodes: Arc<Nodes<P>>,
+    node: usize,
+}
+
+#[async_trait]
+impl<P> PendingView for NodeView<P>
+where
+    P: PubsubClient + 'static,
+    P::Error: 'static,
+{
+    fn name(&self) -> &str {
+        &self.nodes.nodes[self.node].name
+    }
+
+    async fn pending_block(&self) -> anyhow::Result<Option<Block<Transaction>>> {
+        let block = self
+            .nodes
+            .request_node(self.node, "eth_getBlockByNumber", &json!(["pending", true]))
+            .await
+            .map_err(ProviderError::from)?;
+        Ok(serde_json::from_value(block)?)
+    }
+}
+
+impl<P> Node<P> {
+    fn on_success(&self, latency: Duration) {
+        self.metrics.latency.record(latency);
+        let mut health = self.health.lock().unwrap();
+        if health.unhealthy_until.take().is_some() {
+            info!(node = self.name, "node recovered");
+        }
+        health.consecutive_failures = 0;
+        self.metrics.healthy.set(1.);
+    }
+
+    fn on_failure(&self) {
+        self.metrics.failures.increment(1);
+        let mut health = self.health.lock().unwrap();
+        health.consecutive_failures += 1;
+        if health.consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+            if health.unhealthy_until.is_none() {
+                warn!(node = self.name, "node is unhealthy");
+            }
+            health.unhealthy_until = Some(Instant::now() + COOL_DOWN);
+            self.metrics.healthy.set(0.);
+        }
+    }
+}
+
+impl<P> Nodes<P>
+where
+    P: PubsubClient + 'static,
+    P::Error: 'static,
+{
+    /// Indices of nodes in order they should be tried: healthy ones
+    /// by priority followed by ones which are about to recover first
+    fn by_health(&self) -> Vec<usize> {
+        let now = Instant::now();
+        let mut order: Vec<_> = (0..self.nodes.len()).collect();
+        // stable, so priority is kept among healthy ones
+        order.sort_by_key(|&i| {
+            self.nodes[i]
+                .health
+                .lock()
+                .unwrap()
+                .unhealthy_until
+                .filter(|until| *until > now)
+        });
+        order
+    }
+
+    async fn request(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, FailoverProviderError<P::Error>> {
+        let mut last_err = None;
+        for i in self.by_health() {
+            match self.request_node(i, method, &params).await {
+                Ok(result) => return Ok(result),
+                // node is fine if it has responded with an error
+                Err(err) if err.as_error_response().is_some() => return Err(err),
+                Err(err) => {
+                    warn!(node = self.nodes[i].name, method, %err, "request failed, failing over...");
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(last_err.expect("at least one node"))
+    }
+
+    /// Requests given node only, keeping track of its health
+    async fn request_node(
+        &self,
+        i: usize,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, FailoverProviderError<P::Error>> {
+        let node = &self.nodes[i];
+        node.metrics.requests.increment(1);
+        let started_at = Instant::now();
+        match timeout(self.timeout, node.client.request(method, params)).await {
+            Ok(Ok(result)) => {
+                node.on_success(started_at.elapsed());
+                Ok(result)
+            }
+            Ok(Err(err)) if err.as_error_response().is_some() => {
+                node.on_success(started_at.elapsed());
+                Err(FailoverProviderError::Inner(err))
+            }
+            Ok(Err(err)) => {
+                node.on_failure();
+                Err(FailoverProviderError::Inner(err))
+            }
+            Err(_) => {
+                node.on_failure();
+                Err(FailoverProviderError::Timeout(self.timeout))
+            }
+        }
+    }
+
+    /// Returns id of subscription to the node along with its notifications
+    async fn subscribe_node(
+        &self,
+        i: usize,
+        params: &Value,
+    ) -> Result<(U256, NodeNotifications), FailoverProviderError<P::Error>> {
+        let node = &self.nodes[i];
+        let id: U256 = match timeout(self.timeout, node.client.request("eth_subscribe", params)).await
+        {
+            Ok(Ok(id)) => id,
+            Ok(Err(err)) => {
+                node.on_failure();
+                return Err(FailoverProviderError::Inner(err));
+            }
+            Err(_) => {
+                node.on_failure();
+                return Err(FailoverProviderError::Timeout(self.timeout));
+            }
+        };
+        let notifications = node
+            .client
+            .subscribe(id)
+            .map_err(FailoverProviderError::Inner)?;
+        debug!(node = node.name, %id, "subscribed");
+        // end of the stream is reported, so that subscription can be replaced
+        Ok((
+            id,
+            notifications
+                .map(Some)
+                .chain(stream::once(async { None }))
+                .map(move |notification| (i, notification))
+                .boxed(),
+        ))
+    }
+
+    /// Cancels subscription to the node, so that it stops sending notifications
+    fn unsubscribe_node(&self, i: usize, id: U256) {
+        let node = &self.nodes[i];
+        match node.client.unsubscribe(id) {
+            Ok(()) => debug!(node = node.name, %id, "unsubscribed"),
+            Err(err) => debug!(node = node.name, %id, %err, "failed to unsubscribe"),
+        }
+    }
+
+    async fn subscribe(self: Arc<Self>, params: Value) -> Result<U256, FailoverProviderError<P::Error>> {
+        let mut streams = SelectAll::new();
+        let mut used = Vec::new();
+        let mut last_err = None;
+        for i in self.by_health() {
+            if used.len() == SUBSCRIPTIONS_PER_KIND {
+                break;
+            }
+            match self.subscribe_node(i, &params).await {
+                Ok((id, stream)) => {
+                    streams.push(stream);
+                    used.push((i, id));
+                }
+                Err(err) => {
+                    warn!(node = self.nodes[i].name, %err, "failed to subscribe");
+                    last_err = Some(err);
+                }
+            }
+        }
+        if used.is_empty() {
+            return Err(last_err.expect("at least one node"));
+        }
+
+        let id = U256::from(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let (tx, rx) = mpsc::unbounded_channel();
+        let stop = CancellationToken::new();
+        tokio::spawn(self.clone().forward(params, used, streams, tx, stop.clone()));
+        self.subscriptions.lock().unwrap().insert(
+            id,
+            Subscription {
+                notifications: Some(rx),
+                _stop: stop.drop_guard(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// Forwards notifications from subscriptions to nodes dropping
+    /// duplicates and replaces subscriptions of failed nodes. `used` are
+    /// nodes subscribed to along with ids of their subscriptions, which
+    /// are cancelled once forwarding stops.
+    async fn forward(
+        self: Arc<Self>,
+        params: Value,
+        mut used: Vec<(usize, U256)>,
+        mut streams: SelectAll<NodeNotifications>,
+        tx: mpsc::UnboundedSender<Box<RawValue>>,
+        stop: CancellationToken,
+    ) {
+        let mut seen = Seen::default();
+        let mut resubscribe = interval(RESUBSCRIBE_INTERVAL);
+        resubscribe.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = stop.cancelled() => break,
+                _ = tx.closed() => break,
+                Some((i, notification)) = streams.next() => {
+                    let node = &self.nodes[i];
+                    let Some(notification) = notification else {
+                        warn!(node = node.name, "subscription ended");
+                        node.on_failure();
+                        if let Some(pos) = used.iter().position(|&(u, _)| u == i) {
+                            let (_, id) = used.remove(pos);
+                            self.unsubscribe_node(i, id);
+                        }
+                        continue;
+                    };
+                    node.metrics.notifications.increment(1);
+                    if seen.insert(&notification) {
+                        node.metrics.first_notifications.increment(1);
+                        if tx.send(notification).is_err() {
+                            break;
+                        }
+                    }
+                },
+                _ = resubscribe.tick(), if used.len() < SUBSCRIPTIONS_PER_KIND.min(self.nodes.len()) => {
+                    for i in self.by_health() {
+                        if used.len() == SUBSCRIPTIONS_PER_KIND {
+                            break;
+                        }
+                        if used.iter().any(|&(u, _)| u == i) {
+                            continue;
+                        }
+                        match self.subscribe_node(i, &params).await {
+                            Ok((id, stream)) => {
+                                info!(node = self.nodes[i].name, "resubscribed");
+                                streams.push(stream);
+                                used.push((i, id));
+                            }
+                            Err(err) => {
+                                debug!(node = self.nodes[i].name, %err, "failed to resubscribe");
+                            }
+                        }
+                    }
+                },
+            }
+        }
+        for (i, id) in used {
+            self.unsubscribe_node(i, id);
+        }
+    }
+}
+
+/// Notifications of a subscription to the node with given index,
+/// ending with `None` once the subscription ends
+type NodeNotifications = BoxStream<'static, (usize, Option<Box<RawValue>>)>;
+
+/// Keys of recent notifications, which are hashes for new heads
+#[derive(Default)]
+struct Seen {
+    order: VecDeque<String>,
+    keys: HashSet<String>,
+}
+
+impl Seen {
+    /// Returns whether notification is seen for the first time
+    fn insert(&mut self, notification: &RawValue) -> bool {
+        let key = serde_json::from_str::<Value>(notification.get())
+            .ok()
+            .and_then(|value| value.get("hash").map(Value::to_string))
+            .unwrap_or_else(|| notification.get().to_string());
+        if !self.keys.insert(key.clone()) {
+            return false;
+        }
+        self.order.push_back(key);
+        if self.order.len() > SEEN_NOTIFICATIONS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+impl<P: Debug> Debug for FailoverProvider<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FailoverProvider")
+            .field(
+                "nodes",
+                &self.0.nodes.iter().map(|node| &node.name).collect::<Vec<_>>(),
+            )
+            .field("timeout", &self.0.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P> JsonRpcClient for FailoverProvider<P>
+where
+    P: PubsubClient + 'static,
+    P::Error: 'static,
+{
+    type Error = FailoverProviderError<P::Error>;
+
+    fn request<'life0, 'life1, 'async_trait, T, R>(
+        &'life0 self,
+        method: &'life1 str,
+        params: T,
+    ) -> Pin<Box<dyn Future<Output = Result<R, Self::Error>> + marker::Send + 'async_trait>>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+        T: 'async_trait,
+        R: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+    {
+        async move {
+            let params = serde_json::to_value(&params)?;
+            let result = match method {
+                "eth_subscribe" => json!(self.0.clone().subscribe(params).await?),
+                "eth_unsubscribe" => {
+                    let id: U256 =
+                        serde_json::from_value(params.get(0).cloned().unwrap_or_default())?;
+                    json!(self.0.subscriptions.lock().unwrap().remove(&id).is_some())
+                }
+                _ => self.0.request(method, params).await?,
+            };
+            Ok(serde_json::from_value(result)?)
+        }
+        .boxed()
+    }
+}
+
+impl<P> PubsubClient for FailoverProvider<P>
+where
+    P: PubsubClient + 'static,
+    P::Error: 'static,
+{
+    type NotificationStream = BoxStream<'static, Box<RawValue>>;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        let id = id.into();
+        let notifications = self
+            .0
+            .subscriptions
+            .lock()
+            .unwrap()
+            .get_mut(&id)
+            .and_then(|subscription| subscription.notifications.take())
+            .ok_or(FailoverProviderError::UnknownSubscription(id))?;
+        Ok(UnboundedReceiverStream::new(notifications).boxed())
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        self.0.subscriptions.lock().unwrap().remove(&id.into());
+        Ok(())
+    }
+}
+
+struct NodeMetrics {
+    requests: Counter,
+    failures: Counter,
+    latency: Histogram,
+    /// 1 if node is healthy as of the last request to it
+    healthy: Gauge,
+    notifications: Counter,
+    /// Notifications this node delivered before others
+    first_notifications: Counter,
+}
+
+impl NodeMetrics {
+    fn new(node: &str) -> Self {
+        let node = node.to_string();
+        let metrics = Self {
+            requests: register_counter!("sandwitch_node_requests", "node" => node.clone()),
+            failures: register_counter!("sandwitch_node_failures", "node" => node.clone()),
+            latency: register_histogram!("sandwitch_node_latency_duration", "node" => node.clone()),
+            healthy: register_gauge!("sandwitch_node_healthy", "node" => node.clone()),
+            notifications: register_counter!("sandwitch_node_notifications", "node" => node.clone()),
+            first_notifications: register_counter!(
+                "sandwitch_node_first_notifications",
+                "node" => node,
+            ),
+        };
+        metrics.healthy.set(1.);
+        metrics
+    }
+}
+
+#[derive(ThisError, Debug)]
+pub enum FailoverProviderError<P> {
+    #[error("timeout exceeded: {0:?}")]
+    Timeout(Duration),
+
+    #[error("unknown subscription: {0}")]
+    UnknownSubscription(U256),
+
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Inner(P),
+}
+
+impl<P> RpcError for FailoverProviderError<P>
+where
+    P: Into<ProviderError> + RpcError,
+{
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Inner(inner) => inner.as_error_response(),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Inner(inner) => inner.as_serde_error(),
+            Self::Serde(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl<P> From<FailoverProviderError<P>> for ProviderError
+where
+    P: Into<ProviderError> + RpcError + 'static,
+{
+    fn from(e: FailoverProviderError<P>) -> Self {
+        match e {
+            FailoverProviderError::Inner(e) => e.into(),
+            FailoverProviderError::Serde(e) => e.into(),
+            e => ProviderError::JsonRpcClientError(Box::new(e) as Box<dyn RpcError + Send + Sync>),
+        }
+    }
+}
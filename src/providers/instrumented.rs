@@ -0,0 +1,231 @@
+use core::{fmt::Debug, future::Future, marker, pin::Pin};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use ethers::{
+    providers::{JsonRpcClient, JsonRpcError, ProviderError, PubsubClient, RpcError},
+    types::U256,
+};
+use futures::FutureExt;
+use impl_tools::autoimpl;
+use metrics::{register_counter, register_histogram, Counter, Histogram};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::value::{to_raw_value, RawValue};
+use thiserror::Error as ThisError;
+use tokio::time::Instant;
+use tracing::{field, info_span, Instrument, Span};
+
+/// Records per-method latency, errors and sizes of requests and
+/// responses, each request is made within its own `rpc` span
+#[autoimpl(Deref using self.inner)]
+pub struct InstrumentedProvider<P> {
+    inner: P,
+    next_id: AtomicU64,
+    metrics: Mutex<HashMap<String, MethodMetrics>>,
+}
+
+impl<P> InstrumentedProvider<P> {
+    pub fn new(client: P) -> Self {
+        Self {
+            inner: client,
+            next_id: AtomicU64::new(1),
+            metrics: Default::default(),
+        }
+    }
+
+    fn metrics(&self, method: &str) -> MethodMetrics {
+        self.metrics
+            .lock()
+            .unwrap()
+            .entry(method.to_string())
+            .or_insert_with(|| MethodMetrics::new(method))
+            .clone()
+    }
+}
+
+/// Method to label metrics with, pending block is requested by the
+/// same method as the others but takes much longer
+fn method_label<'a>(method: &'a str, params: &RawValue) -> &'a str {
+    match method {
+        "eth_getBlockByNumber" if params.get().starts_with(r#"["pending""#) => {
+            "eth_getBlockByNumber(pending)"
+        }
+        _ => method,
+    }
+}
+
+/// Class of the error to count errors by
+fn error_class<E: RpcError>(err: &E) -> &'static str {
+    if err.as_error_response().is_some() {
+        return "rpc";
+    }
+    if err.as_serde_error().is_some() {
+        return "serde";
+    }
+    if err.to_string().to_lowercase().contains("timeout") {
+        return "timeout";
+    }
+    "transport"
+}
+
+impl<P: Debug> Debug for InstrumentedProvider<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("InstrumentedProvider")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P> JsonRpcClient for InstrumentedProvider<P>
+where
+    P: JsonRpcClient,
+    P::Error: 'static,
+{
+    type Error = InstrumentedProviderError<P::Error>;
+
+    fn request<'life0, 'life1, 'async_trait, T, R>(
+        &'life0 self,
+        method: &'life1 str,
+        params: T,
+    ) -> Pin<Box<dyn Future<Output = Result<R, Self::Error>> + marker::Send + 'async_trait>>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+        T: 'async_trait,
+        R: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let span = info_span!(
+            "rpc",
+            rpc.method = method,
+            rpc.id = id,
+            rpc.request_size = field::Empty,
+            rpc.response_size = field::Empty,
+            rpc.error = field::Empty,
+        );
+        async move {
+            let params = to_raw_value(&params)?;
+            let metrics = self.metrics(method_label(method, &params));
+            metrics.request_size.record(params.get().len() as f64);
+            Span::current().record("rpc.request_size", params.get().len());
+
+            let started_at = Instant::now();
+            let response: Result<Box<RawValue>, _> = self.inner.request(method, &params).await;
+            metrics.duration.record(started_at.elapsed());
+            let response = response.map_err(|err| {
+                let class = error_class(&err);
+                Span::current().record("rpc.error", class);
+                metrics.errors(class).increment(1);
+                InstrumentedProviderError::Inner(err)
+            })?;
+
+            metrics.response_size.record(response.get().len() as f64);
+            Span::current().record("rpc.response_size", response.get().len());
+            Ok(serde_json::from_str(response.get())?)
+        }
+        .instrument(span)
+        .boxed()
+    }
+}
+
+impl<P> PubsubClient for InstrumentedProvider<P>
+where
+    P: PubsubClient,
+    P::Error: 'static,
+{
+    type NotificationStream = P::NotificationStream;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        self.inner
+            .subscribe(id)
+            .map_err(InstrumentedProviderError::Inner)
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        self.inner
+            .unsubscribe(id)
+            .map_err(InstrumentedProviderError::Inner)
+    }
+}
+
+#[derive(Clone)]
+struct MethodMetrics {
+    method: String,
+    duration: Histogram,
+    request_size: Histogram,
+    response_size: Histogram,
+}
+
+impl MethodMetrics {
+    fn new(method: &str) -> Self {
+        let method = method.to_string();
+        Self {
+            duration: register_histogram!("sandwitch_rpc_duration", "method" => method.clone()),
+            request_size: register_histogram!(
+                "sandwitch_rpc_request_bytes",
+                "method" => method.clone(),
+            ),
+            response_size: register_histogram!(
+                "sandwitch_rpc_response_bytes",
+                "method" => method.clone(),
+            ),
+            method,
+        }
+    }
+
+    fn errors(&self, class: &'static str) -> Counter {
+        register_counter!(
+            "sandwitch_rpc_errors",
+            "method" => self.method.clone(),
+            "class" => class,
+        )
+    }
+}
+
+#[derive(ThisError, Debug)]
+pub enum InstrumentedProviderError<P> {
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Inner(P),
+}
+
+impl<P> RpcError for InstrumentedProviderError<P>
+where
+    P: Into<ProviderError> + RpcError,
+{
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Inner(inner) => inner.as_error_response(),
+            Self::Serde(_) => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Inner(inner) => inner.as_serde_error(),
+            Self::Serde(err) => Some(err),
+        }
+    }
+}
+
+impl<P> From<InstrumentedProviderError<P>> for ProviderError
+where
+    P: Into<ProviderError> + RpcError + 'static,
+{
+    fn from(e: InstrumentedProviderError<P>) -> Self {
+        match e {
+            InstrumentedProviderError::Inner(e) => e.into(),
+            InstrumentedProviderError::Serde(e) => e.into(),
+        }
+    }
+}
@@ -1,2 +1,10 @@
+pub mod batch;
+pub mod failover;
+pub mod instrumented;
 pub mod one_of;
+pub mod polling;
+pub mod rate_limit;
+pub mod record;
+pub mod replay;
+pub mod retry;
 pub mod timeout;
@@ -0,0 +1,399 @@
+use core::{fmt::Debug, future::Future, marker, pin::Pin, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use ethers::{
+    providers::{JsonRpcClient, JsonRpcError, ProviderError, PubsubClient, RpcError},
+    types::{TxHash, U256, U64},
+};
+use futures::{
+    future,
+    stream::{BoxStream, StreamExt},
+    FutureExt, TryFutureExt,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, value::RawValue, Value};
+use thiserror::Error as ThisError;
+use tokio::{
+    sync::mpsc,
+    time::{sleep, sleep_until, Instant},
+};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_util::sync::{CancellationToken, DropGuard};
+use tracing::{debug, warn};
+
+/// Minimal interval between polls
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Number of polls per estimated block interval once next head is expected
+const POLLS_PER_BLOCK: u32 = 20;
+/// Polling is not started until this fraction of the estimated block
+/// interval has passed since the last head
+const QUIET_FRACTION: f64 = 0.75;
+/// Heads behind the latest one to be emitted after missed polls
+const MAX_CATCH_UP: u64 = 3;
+
+/// Emulates `eth_subscribe` over request-response transports like HTTP
+/// by polling the inner provider: `newHeads` are detected with
+/// `eth_blockNumber` and `newPendingTransactions` are taken from filter.
+/// Polling interval is adapted to the estimated block interval, so that
+/// node is queried often only when the next head is expected.
+pub struct PollingProvider<P> {
+    inner: Arc<P>,
+    block_interval: Duration,
+    next_id: AtomicU64,
+    subscriptions: Mutex<HashMap<U256, Subscription>>,
+}
+
+struct Subscription {
+    /// Taken on [`PubsubClient::subscribe()`]
+    notifications: Option<mpsc::UnboundedReceiver<Box<RawValue>>>,
+    _stop: DropGuard,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum SubscriptionKind {
+    NewHeads,
+    NewPendingTransactions,
+}
+
+impl<P> PollingProvider<P> {
+    /// Polling interval is tuned starting from given `block_interval`
+    pub fn new(client: P, block_interval: Duration) -> Self {
+        Self {
+            inner: Arc::new(client),
+            block_interval,
+            next_id: AtomicU64::new(1),
+            subscriptions: Default::default(),
+        }
+    }
+}
+
+impl<P> PollingProvider<P>
+where
+    P: JsonRpcClient + 'static,
+{
+    fn subscribe_with_polling(&self, kind: SubscriptionKind) -> U256 {
+        let id = U256::from(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let (tx, rx) = mpsc::unbounded_channel();
+        let stop = CancellationToken::new();
+
+        let poller = Poller {
+            client: self.inner.clone(),
+            notifications: tx,
+            schedule: PollSchedule::new(self.block_interval),
+        };
+        let stopped = stop.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = stopped.cancelled() => {},
+                _ = poller.run(kind) => {},
+            }
+        });
+        debug!(?kind, %id, "polling started");
+
+        self.subscriptions.lock().unwrap().insert(
+            id,
+            Subscription {
+                notifications: Some(rx),
+                _stop: stop.drop_guard(),
+            },
+        );
+        id
+    }
+
+    fn stop_polling(&self, id: U256) -> bool {
+        self.subscriptions.lock().unwrap().remove(&id).is_some()
+    }
+}
+
+impl<P: Debug> Debug for PollingProvider<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PollingProvider")
+            .field("inner", &self.inner)
+            .field("block_interval", &self.block_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P> JsonRpcClient for PollingProvider<P>
+where
+    P: JsonRpcClient + 'static,
+    P::Error: 'static,
+{
+    type Error = PollingProviderError<P::Error>;
+
+    fn request<'life0, 'life1, 'async_trait, T, R>(
+        &'life0 self,
+        method: &'life1 str,
+        params: T,
+    ) -> Pin<Box<dyn Future<Output = Result<R, Self::Error>> + marker::Send + 'async_trait>>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+        T: 'async_trait,
+        R: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+    {
+        let result = match method {
+            "eth_subscribe" => serde_json::to_value(&params)
+                .map_err(PollingProviderError::from)
+                .and_then(|params| {
+                    let kind = match params.get(0).and_then(Value::as_str) {
+                        Some("newHeads") => SubscriptionKind::NewHeads,
+                        Some("newPendingTransactions") => SubscriptionKind::NewPendingTransactions,
+                        kind => {
+                            return Err(PollingProviderError::UnsupportedSubscription(
+                                kind.unwrap_or_default().to_string(),
+                            ))
+                        }
+                    };
+                    Ok(json!(self.subscribe_with_polling(kind)))
+                }),
+            "eth_unsubscribe" => serde_json::to_value(&params)
+                .map_err(PollingProviderError::from)
+                .and_then(|params| {
+                    let id = serde_json::from_value(params.get(0).cloned().unwrap_or_default())?;
+                    Ok(json!(self.stop_polling(id)))
+                }),
+            _ => {
+                return self
+                    .inner
+                    .request(method, params)
+                    .map_err(PollingProviderError::Inner)
+                    .boxed()
+            }
+        };
+        future::ready(result.and_then(|result| serde_json::from_value(result).map_err(Into::into)))
+            .boxed()
+    }
+}
+
+impl<P> PubsubClient for PollingProvider<P>
+where
+    P: JsonRpcClient + 'static,
+    P::Error: 'static,
+{
+    type NotificationStream = BoxStream<'static, Box<RawValue>>;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        let id = id.into();
+        let notifications = self
+            .subscriptions
+            .lock()
+            .unwrap()
+            .get_mut(&id)
+            .and_then(|subscription| subscription.notifications.take())
+            .ok_or(PollingProviderError::UnknownSubscription(id))?;
+        Ok(UnboundedReceiverStream::new(notifications).boxed())
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        self.stop_polling(id.into());
+        Ok(())
+    }
+}
+
+/// Decides when to poll next based on observed heads
+struct PollSchedule {
+    /// Estimated block interval, moving average of observed ones
+    block_interval: Duration,
+    last_head: Option<(U64, Instant)>,
+}
+
+impl PollSchedule {
+    fn new(block_interval: Duration) -> Self {
+        Self {
+            block_interval,
+            last_head: None,
+        }
+    }
+
+    fn on_head(&mut self, number: U64, at: Instant) {
+        if let Some((last_number, last_at)) = self.last_head {
+            let blocks = (number - last_number).as_u32().max(1);
+            let observed = (at - last_at) / blocks;
+            self.block_interval = (self.block_interval * 7 + observed) / 8;
+        }
+        self.last_head = Some((number, at));
+    }
+
+    fn poll_interval(&self) -> Duration {
+        (self.block_interval / POLLS_PER_BLOCK).max(MIN_POLL_INTERVAL)
+    }
+
+    /// Waits quietly after the last head and then polls frequently
+    async fn wait(&self) {
+        let quiet_until = self
+            .last_head
+            .map(|(_, at)| at + self.block_interval.mul_f64(QUIET_FRACTION));
+        match quiet_until {
+            Some(until) if until > Instant::now() => sleep_until(until).await,
+            _ => sleep(self.poll_interval()).await,
+        }
+    }
+}
+
+struct Poller<P> {
+    client: Arc<P>,
+    notifications: mpsc::UnboundedSender<Box<RawValue>>,
+    schedule: PollSchedule,
+}
+
+impl<P> Poller<P>
+where
+    P: JsonRpcClient,
+{
+    /// Polls until subscription is dropped
+    async fn run(mut self, kind: SubscriptionKind) {
+        match kind {
+            SubscriptionKind::NewHeads => self.poll_heads().await,
+            SubscriptionKind::NewPendingTransactions => self.poll_pending_txs().await,
+        }
+    }
+
+    async fn poll_heads(&mut self) {
+        loop {
+            if let Err(err) = self.poll_head().await {
+                warn!(%err, "failed to poll new heads");
+            }
+            if self.notifications.is_closed() {
+                return;
+            }
+            self.schedule.wait().await;
+        }
+    }
+
+    async fn poll_head(&mut self) -> Result<(), P::Error> {
+        let number: U64 = self.client.request("eth_blockNumber", ()).await?;
+        let received_at = Instant::now();
+        let from = match self.schedule.last_head {
+            Some((last, _)) if number <= last => return Ok(()),
+            Some((last, _)) => (last + 1).max(number.saturating_sub(MAX_CATCH_UP.into())),
+            None => number,
+        };
+        let mut emitted = None;
+        let mut result = Ok(());
+        for n in from.as_u64()..=number.as_u64() {
+            let block: Option<Box<RawValue>> = match self
+                .client
+                .request("eth_getBlockByNumber", (U64::from(n), false))
+                .await
+            {
+                Ok(block) => block,
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            };
+            // node can be behind its own block number behind load balancer
+            let Some(block) = block else {
+                break;
+            };
+            if self.notifications.send(block).is_err() {
+                break;
+            }
+            emitted = Some(U64::from(n));
+        }
+        // recorded even if the range is cut short, so that heads
+        // already sent are not sent again on the next poll
+        if let Some(emitted) = emitted {
+            self.schedule.on_head(emitted, received_at);
+        }
+        result
+    }
+
+    async fn poll_pending_txs(&mut self) {
+        let mut filter: Option<U256> = None;
+        loop {
+            let polled = async {
+                let id = match filter {
+                    Some(id) => id,
+                    None => *filter.insert(
+                        self.client
+                            .request("eth_newPendingTransactionFilter", ())
+                            .await?,
+                    ),
+                };
+                self.client
+                    .request::<_, Vec<TxHash>>("eth_getFilterChanges", [id])
+                    .await
+            }
+            .await;
+            match polled {
+                Ok(hashes) => {
+                    for hash in hashes {
+                        let hash = serde_json::value::to_raw_value(&hash)
+                            .expect("hash is always serializable");
+                        if self.notifications.send(hash).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(err) => {
+                    // filters expire if not polled for a while
+                    warn!(%err, "failed to poll pending transactions, recreating filter...");
+                    filter = None;
+                }
+            }
+            if self.notifications.is_closed() {
+                return;
+            }
+            sleep(self.schedule.poll_interval()).await;
+        }
+    }
+}
+
+#[derive(ThisError, Debug)]
+pub enum PollingProviderError<P> {
+    #[error("subscription to {0:?} is not supported")]
+    UnsupportedSubscription(String),
+
+    #[error("unknown subscription: {0}")]
+    UnknownSubscription(U256),
+
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Inner(P),
+}
+
+impl<P> RpcError for PollingProviderError<P>
+where
+    P: Into<ProviderError> + RpcError,
+{
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Inner(inner) => inner.as_error_response(),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Inner(inner) => inner.as_serde_error(),
+            Self::Serde(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl<P> From<PollingProviderError<P>> for ProviderError
+where
+    P: Into<ProviderError> + RpcError + 'static,
+{
+    fn from(e: PollingProviderError<P>) -> Self {
+        match e {
+            PollingProviderError::Inner(e) => e.into(),
+            PollingProviderError::Serde(e) => e.into(),
+            e => ProviderError::JsonRpcClientError(Box::new(e) as Box<dyn RpcError + Send + Sync>),
+        }
+    }
+}
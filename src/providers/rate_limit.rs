@@ -0,0 +1,153 @@
+use core::{fmt::Debug, future::Future, marker, pin::Pin, time::Duration};
+use std::sync::Mutex;
+
+use ethers::{
+    providers::{JsonRpcClient, PubsubClient},
+    types::U256,
+};
+use futures::FutureExt;
+use impl_tools::autoimpl;
+use metrics::{register_histogram, Histogram};
+use serde::{
+    de::{self, DeserializeOwned},
+    Deserialize, Deserializer, Serialize,
+};
+use tokio::time::{sleep, Instant};
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct RateLimitConfig {
+    /// Sustained rate of requests
+    #[serde(deserialize_with = "deserialize_rate")]
+    pub requests_per_sec: f64,
+    /// Requests which can be made at once after being idle,
+    /// defaults to one second worth of requests
+    pub burst: Option<f64>,
+}
+
+/// Requests would never be made with non-positive rate
+fn deserialize_rate<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let rate = f64::deserialize(deserializer)?;
+    if rate.is_nan() || rate <= 0. {
+        return Err(de::Error::custom(format!(
+            "rate limit must be positive, got {rate}"
+        )));
+    }
+    Ok(rate)
+}
+
+/// Keeps requests under the rate limit of the node with a token bucket,
+/// delaying the ones exceeding it instead of letting the node reject them
+#[autoimpl(Deref using self.inner)]
+pub struct RateLimitProvider<P> {
+    inner: P,
+    /// Unlimited if not set
+    bucket: Option<Mutex<TokenBucket>>,
+    waited: Histogram,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    /// Negative if requests are waiting for tokens
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(cfg: RateLimitConfig) -> Self {
+        let burst = cfg.burst.unwrap_or(cfg.requests_per_sec).max(1.);
+        Self {
+            rate: cfg.requests_per_sec,
+            burst,
+            tokens: burst,
+            updated_at: Instant::now(),
+        }
+    }
+
+    /// Takes a token returning time to wait until it is available
+    fn take(&mut self) -> Duration {
+        let now = Instant::now();
+        self.tokens =
+            (self.tokens + (now - self.updated_at).as_secs_f64() * self.rate).min(self.burst) - 1.;
+        self.updated_at = now;
+        if self.tokens >= 0. {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-self.tokens / self.rate)
+    }
+}
+
+impl<P> RateLimitProvider<P> {
+    /// Requests are not limited if `cfg` is not set
+    pub fn new(client: P, cfg: impl Into<Option<RateLimitConfig>>) -> Self {
+        Self {
+            inner: client,
+            bucket: cfg.into().map(|cfg| Mutex::new(TokenBucket::new(cfg))),
+            waited: register_histogram!("sandwitch_rpc_rate_limit_wait_duration"),
+        }
+    }
+}
+
+impl<P: Debug> Debug for RateLimitProvider<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RateLimitProvider")
+            .field("inner", &self.inner)
+            .field("bucket", &self.bucket)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P> JsonRpcClient for RateLimitProvider<P>
+where
+    P: JsonRpcClient,
+{
+    type Error = P::Error;
+
+    fn request<'life0, 'life1, 'async_trait, T, R>(
+        &'life0 self,
+        method: &'life1 str,
+        params: T,
+    ) -> Pin<Box<dyn Future<Output = Result<R, Self::Error>> + marker::Send + 'async_trait>>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+        T: 'async_trait,
+        R: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+    {
+        async move {
+            // taken once polled, so that dropped requests don't use tokens
+            let wait = self
+                .bucket
+                .as_ref()
+                .map_or(Duration::ZERO, |bucket| bucket.lock().unwrap().take());
+            if !wait.is_zero() {
+                self.waited.record(wait);
+                sleep(wait).await;
+            }
+            self.inner.request(method, params).await
+        }
+        .boxed()
+    }
+}
+
+impl<P> PubsubClient for RateLimitProvider<P>
+where
+    P: PubsubClient,
+{
+    type NotificationStream = P::NotificationStream;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        self.inner.subscribe(id)
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        self.inner.unsubscribe(id)
+    }
+}
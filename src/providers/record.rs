@@ -0,0 +1,260 @@
+use core::{
+    fmt::Debug,
+    future::Future,
+    marker,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use ethers::{
+    providers::{JsonRpcClient, JsonRpcError, ProviderError, PubsubClient, RpcError},
+    types::U256,
+};
+use futures::{FutureExt, Stream};
+use pin_project::pin_project;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{value::RawValue, Value};
+use thiserror::Error as ThisError;
+use tokio::time::Instant;
+use tracing::error;
+
+/// Single line of a recording
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Recorded {
+    /// Milliseconds since recording has started
+    pub at_ms: u64,
+    #[serde(flatten)]
+    pub event: RecordedEvent,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecordedEvent {
+    /// New head received via `newHeads` subscription
+    Head { block: Value },
+    /// Response to JSON-RPC request
+    Response {
+        method: String,
+        params: Value,
+        response: Result<Value, JsonRpcError>,
+    },
+}
+
+/// Writes recorded events as JSON lines
+pub struct Recorder {
+    started_at: Instant,
+    out: Mutex<BufWriter<File>>,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            started_at: Instant::now(),
+            out: Mutex::new(BufWriter::new(File::create(path)?)),
+        })
+    }
+
+    fn record(&self, event: RecordedEvent) {
+        let flush = matches!(event, RecordedEvent::Head { .. });
+        let recorded = Recorded {
+            at_ms: self.started_at.elapsed().as_millis() as u64,
+            event,
+        };
+        let mut out = self.out.lock().unwrap();
+        if let Err(err) = serde_json::to_writer(&mut *out, &recorded)
+            .map_err(io::Error::from)
+            .and_then(|()| out.write_all(b"\n"))
+            .and_then(|()| if flush { out.flush() } else { Ok(()) })
+        {
+            error!(%err, "failed to record");
+        }
+    }
+}
+
+impl Debug for Recorder {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Recorder")
+            .field("started_at", &self.started_at)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Records new heads and responses of the inner provider,
+/// so that they can be replayed later with [`ReplayProvider`](super::replay::ReplayProvider)
+#[derive(Debug)]
+pub struct RecordingProvider<P> {
+    inner: P,
+    recorder: Arc<Recorder>,
+    heads_subscriptions: Mutex<HashSet<U256>>,
+}
+
+impl<P> RecordingProvider<P> {
+    pub fn new(client: P, recorder: Recorder) -> Self {
+        Self {
+            inner: client,
+            recorder: Arc::new(recorder),
+            heads_subscriptions: Default::default(),
+        }
+    }
+}
+
+impl<P> JsonRpcClient for RecordingProvider<P>
+where
+    P: JsonRpcClient,
+    P::Error: 'static,
+{
+    type Error = RecordingProviderError<P::Error>;
+
+    fn request<'life0, 'life1, 'async_trait, T, R>(
+        &'life0 self,
+        method: &'life1 str,
+        params: T,
+    ) -> Pin<Box<dyn Future<Output = Result<R, Self::Error>> + marker::Send + 'async_trait>>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+        T: 'async_trait,
+        R: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+    {
+        async move {
+            let params = serde_json::to_value(&params).map_err(RecordingProviderError::Serde)?;
+            let result = match self.inner.request::<_, Value>(method, params.clone()).await {
+                Ok(result) => result,
+                Err(err) => {
+                    // transport errors are not worth replaying
+                    if let Some(response) = err.as_error_response() {
+                        self.recorder.record(RecordedEvent::Response {
+                            method: method.to_string(),
+                            params,
+                            response: Err(response.clone()),
+                        });
+                    }
+                    return Err(RecordingProviderError::Inner(err));
+                }
+            };
+
+            if method == "eth_subscribe" && params.get(0).is_some_and(|kind| kind == "newHeads") {
+                if let Ok(id) = serde_json::from_value(result.clone()) {
+                    self.heads_subscriptions.lock().unwrap().insert(id);
+                }
+            }
+            self.recorder.record(RecordedEvent::Response {
+                method: method.to_string(),
+                params,
+                response: Ok(result.clone()),
+            });
+            serde_json::from_value(result).map_err(RecordingProviderError::Serde)
+        }
+        .boxed()
+    }
+}
+
+impl<P> PubsubClient for RecordingProvider<P>
+where
+    P: PubsubClient,
+    P::Error: 'static,
+{
+    type NotificationStream = RecordingStream<P::NotificationStream>;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        let id = id.into();
+        let heads = self.heads_subscriptions.lock().unwrap().contains(&id);
+        Ok(RecordingStream {
+            inner: self
+                .inner
+                .subscribe(id)
+                .map_err(RecordingProviderError::Inner)?,
+            recorder: heads.then(|| self.recorder.clone()),
+        })
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        let id = id.into();
+        self.heads_subscriptions.lock().unwrap().remove(&id);
+        self.inner
+            .unsubscribe(id)
+            .map_err(RecordingProviderError::Inner)
+    }
+}
+
+/// Notifications stream which records new heads going through it
+#[pin_project]
+pub struct RecordingStream<S> {
+    #[pin]
+    inner: S,
+    recorder: Option<Arc<Recorder>>,
+}
+
+impl<S> Stream for RecordingStream<S>
+where
+    S: Stream<Item = Box<RawValue>>,
+{
+    type Item = Box<RawValue>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let item = futures::ready!(this.inner.poll_next(cx));
+        if let (Some(recorder), Some(head)) = (this.recorder, &item) {
+            match serde_json::from_str(head.get()) {
+                Ok(block) => recorder.record(RecordedEvent::Head { block }),
+                Err(err) => error!(%err, "failed to record new head"),
+            }
+        }
+        Poll::Ready(item)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[derive(ThisError, Debug)]
+pub enum RecordingProviderError<P> {
+    #[error(transparent)]
+    Serde(serde_json::Error),
+
+    #[error(transparent)]
+    Inner(P),
+}
+
+impl<P> RpcError for RecordingProviderError<P>
+where
+    P: Into<ProviderError> + RpcError,
+{
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Inner(inner) => inner.as_error_response(),
+            Self::Serde(_) => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Inner(inner) => inner.as_serde_error(),
+            Self::Serde(err) => Some(err),
+        }
+    }
+}
+
+impl<P> From<RecordingProviderError<P>> for ProviderError
+where
+    P: Into<ProviderError> + RpcError + 'static,
+{
+    fn from(e: RecordingProviderError<P>) -> Self {
+        match e {
+            RecordingProviderError::Inner(e) => e.into(),
+            RecordingProviderError::Serde(e) => ProviderError::SerdeJson(e),
+        }
+    }
+}
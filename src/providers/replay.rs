@@ -0,0 +1,486 @@
+use core::{fmt::Debug, future::Future, marker, pin::Pin, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::Context;
+use ethers::{
+    providers::{
+        Http, HttpClientError, JsonRpcClient, JsonRpcError, ProviderError, PubsubClient, RpcError,
+    },
+    types::{Address, Bytes, Transaction, TransactionReceipt, TxHash, U256, U64},
+    utils::{keccak256, rlp},
+};
+use futures::{
+    future,
+    stream::{self, BoxStream, StreamExt},
+    FutureExt,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, value::RawValue, Value};
+use thiserror::Error as ThisError;
+use tokio::time::{sleep, sleep_until, Instant};
+use tokio_util::sync::CancellationToken;
+
+use super::record::{Recorded, RecordedEvent};
+
+const HEADS_SUBSCRIPTION_ID: u64 = 1;
+
+type Responses = HashMap<(String, String), Result<Value, JsonRpcError>>;
+
+/// Position of the block parameter of methods reading state at a block
+const BLOCK_PARAMS: &[(&str, usize)] = &[
+    ("eth_getBalance", 1),
+    ("eth_getCode", 1),
+    ("eth_getTransactionCount", 1),
+    ("eth_getStorageAt", 2),
+    ("eth_call", 1),
+    ("eth_estimateGas", 1),
+    ("eth_createAccessList", 1),
+];
+
+/// Methods whose responses are served for any block if the exact
+/// request was not recorded
+const STATE_METHODS: &[&str] = &[
+    "eth_getBalance",
+    "eth_getCode",
+    "eth_getTransactionCount",
+    "eth_getStorageAt",
+];
+
+struct Head {
+    at: Duration,
+    number: Option<U64>,
+    block: Box<RawValue>,
+}
+
+struct Replay {
+    heads: Vec<Head>,
+    /// Responses recorded before the first head followed by ones
+    /// recorded after each of heads
+    responses: Vec<Responses>,
+    /// Successful responses to state queries in the same order as
+    /// `responses`, keyed by params without the block
+    state: Vec<Responses>,
+    /// Index into `responses` of the head being replayed
+    current: AtomicUsize,
+    report: Mutex<ReplayReport>,
+    finished: CancellationToken,
+    /// Requests missing in the recording are forwarded to it
+    archive: Option<Http>,
+}
+
+/// Serves new heads and responses from a recording made with
+/// [`RecordingProvider`](super::record::RecordingProvider) without any node.
+/// State queries are served from the latest response recorded for the same
+/// account or slot, other missing requests are forwarded to the archive node
+/// if any, pinned to the replayed head.
+/// Transactions are never sent anywhere, but collected into [`ReplayReport`].
+#[derive(Clone)]
+pub struct ReplayProvider(Arc<Replay>);
+
+impl ReplayProvider {
+    pub fn load(path: impl AsRef<Path>, archive: Option<Http>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let recording = fs::read_to_string(path)
+            .with_context(|| format!("failed to read recording '{}'", path.display()))?;
+
+        let mut heads = Vec::new();
+        let mut responses = vec![Responses::new()];
+        let mut state = vec![Responses::new()];
+        for (i, line) in recording.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let recorded: Recorded = serde_json::from_str(line)
+                .with_context(|| format!("invalid record at {}:{}", path.display(), i + 1))?;
+            match recorded.event {
+                RecordedEvent::Head { block } => {
+                    heads.push(Head {
+                        at: Duration::from_millis(recorded.at_ms),
+                        number: block
+                            .get("number")
+                            .cloned()
+                            .and_then(|n| serde_json::from_value(n).ok()),
+                        block: serde_json::value::to_raw_value(&block)?,
+                    });
+                    responses.push(Responses::new());
+                    state.push(Responses::new());
+                }
+                RecordedEvent::Response {
+                    method,
+                    params,
+                    response,
+                } => {
+                    match state_key(&method, &params) {
+                        Some(key) if response.is_ok() => {
+                            state.last_mut().unwrap().insert(key, response.clone());
+                        }
+                        _ => {}
+                    }
+                    responses
+                        .last_mut()
+                        .unwrap()
+                        .insert((method, params.to_string()), response);
+                }
+            }
+        }
+
+        Ok(Self(Arc::new(Replay {
+            heads,
+            responses,
+            state,
+            current: AtomicUsize::new(0),
+            report: Default::default(),
+            finished: CancellationToken::new(),
+            archive,
+        })))
+    }
+
+    /// Cancelled once all recorded heads were replayed
+    /// and processing of the last one had time to finish
+    pub fn finished(&self) -> CancellationToken {
+        self.0.finished.clone()
+    }
+
+    pub fn report(&self) -> ReplayReport {
+        let mut report = self.0.report.lock().unwrap().clone();
+        for tx in &mut report.sent {
+            if let Some(receipt) = self.0.find_receipt(tx.hash) {
+                tx.outcome = SimulatedOutcome::Included {
+                    status: receipt.status,
+                    gas_used: receipt.gas_used,
+                    fee: receipt.gas_used.unwrap_or_default()
+                        * receipt.effective_gas_price.unwrap_or_default(),
+                };
+            }
+        }
+        report
+    }
+}
+
+impl Replay {
+    async fn request(&self, method: &str, params: Value) -> Result<Value, ReplayError> {
+        let params = match self.respond(method, params) {
+            Err(ReplayError::NotRecorded { params, .. }) => params,
+            result => return result,
+        };
+        let Some(archive) = &self.archive else {
+            let mut report = self.report.lock().unwrap();
+            *report.not_recorded.entry(method.to_string()).or_default() += 1;
+            return Err(ReplayError::NotRecorded {
+                method: method.to_string(),
+                params,
+            });
+        };
+        let params = self.pin_to_head(method, params);
+        let result = archive.request(method, params).await;
+        let mut report = self.report.lock().unwrap();
+        *report.from_archive.entry(method.to_string()).or_default() += 1;
+        result.map_err(ReplayError::Archive)
+    }
+
+    fn respond(&self, method: &str, params: Value) -> Result<Value, ReplayError> {
+        match method {
+            "eth_subscribe" if params.get(0).is_some_and(|kind| kind == "newHeads") => {
+                return Ok(json!(U256::from(HEADS_SUBSCRIPTION_ID)));
+            }
+            "eth_unsubscribe" => return Ok(json!(true)),
+            "eth_sendRawTransaction" => {
+                let raw: Bytes = serde_json::from_value(params.get(0).cloned().unwrap_or_default())?;
+                return Ok(json!(self.on_send(raw)?));
+            }
+            _ => {}
+        }
+
+        let current = self.current.load(Ordering::SeqCst);
+        let key = (method.to_string(), params.to_string());
+        // responses recorded during initialization are valid for all of the heads
+        let response = [current, 0]
+            .into_iter()
+            .find_map(|i| self.responses[i].get(&key))
+            .or_else(|| {
+                let key = state_key(method, &params)?;
+                // the latest state recorded up to the replayed head
+                self.state[..=current]
+                    .iter()
+                    .rev()
+                    .find_map(|state| state.get(&key))
+            })
+            .cloned();
+
+        let mut report = self.report.lock().unwrap();
+        match response {
+            Some(response) => {
+                report.served += 1;
+                response.map_err(ReplayError::JsonRpc)
+            }
+            // our own transactions were never sent, so they are never mined either
+            None if method == "eth_getTransactionReceipt" => Ok(Value::Null),
+            None => Err(ReplayError::NotRecorded {
+                method: method.to_string(),
+                params,
+            }),
+        }
+    }
+
+    /// Replaces `latest` and `pending` blocks with the replayed head,
+    /// so that the archive node sees the state the recording was made at
+    fn pin_to_head(&self, method: &str, mut params: Value) -> Value {
+        let head = self
+            .current
+            .load(Ordering::SeqCst)
+            .checked_sub(1)
+            .and_then(|i| self.heads[i].number);
+        if let (Some(head), Some(index), Some(params)) =
+            (head, block_param(method), params.as_array_mut())
+        {
+            match params.get_mut(index) {
+                Some(block) if block.is_null() || *block == "latest" || *block == "pending" => {
+                    *block = json!(head);
+                }
+                Some(_) => {}
+                None => {
+                    params.resize(index, Value::Null);
+                    params.push(json!(head));
+                }
+            }
+        }
+        params
+    }
+
+    fn on_send(&self, raw: Bytes) -> Result<TxHash, ReplayError> {
+        let hash = TxHash::from(keccak256(&raw));
+        let tx: Transaction = rlp::decode(&raw)?;
+        let gas_price = tx.gas_price.or(tx.max_fee_per_gas).unwrap_or_default();
+        let current = self.current.load(Ordering::SeqCst);
+
+        let mut report = self.report.lock().unwrap();
+        report.max_fees += tx.gas * gas_price;
+        report.sent.push(SentTx {
+            head: current
+                .checked_sub(1)
+                .and_then(|i| self.heads[i].number),
+            hash,
+            nonce: tx.nonce,
+            to: tx.to,
+            gas: tx.gas,
+            gas_price,
+            value: tx.value,
+            outcome: SimulatedOutcome::Estimated {
+                max_fee: tx.gas * gas_price,
+            },
+        });
+        Ok(hash)
+    }
+
+    fn find_receipt(&self, hash: TxHash) -> Option<TransactionReceipt> {
+        let key = (
+            "eth_getTransactionReceipt".to_string(),
+            json!([hash]).to_string(),
+        );
+        self.responses
+            .iter()
+            .filter_map(|responses| responses.get(&key)?.as_ref().ok())
+            .find_map(|receipt| serde_json::from_value(receipt.clone()).ok())
+    }
+
+    fn heads(self: Arc<Self>) -> BoxStream<'static, Box<RawValue>> {
+        let started_at = Instant::now();
+        let first_at = self.heads.first().map(|h| h.at).unwrap_or_default();
+        // give the last head as much time to be processed as there was between previous ones
+        let last_interval = self
+            .heads
+            .windows(2)
+            .last()
+            .map(|w| w[1].at - w[0].at)
+            .unwrap_or(Duration::from_secs(3));
+
+        let finished = self.finished.clone();
+        stream::iter(0..self.heads.len())
+            .then(move |i| {
+                let replay = self.clone();
+                async move {
+                    let head = &replay.heads[i];
+                    sleep_until(started_at + (head.at - first_at)).await;
+                    replay.current.store(i + 1, Ordering::SeqCst);
+                    replay.report.lock().unwrap().blocks += 1;
+                    head.block.clone()
+                }
+            })
+            .chain(
+                stream::once(async move {
+                    sleep(last_interval).await;
+                    finished.cancel();
+                })
+                .filter_map(|()| future::ready(None)),
+            )
+            .boxed()
+    }
+}
+
+impl Debug for ReplayProvider {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ReplayProvider")
+            .field("heads", &self.0.heads.len())
+            .field("current", &self.0.current)
+            .finish_non_exhaustive()
+    }
+}
+
+impl JsonRpcClient for ReplayProvider {
+    type Error = ReplayError;
+
+    fn request<'life0, 'life1, 'async_trait, T, R>(
+        &'life0 self,
+        method: &'life1 str,
+        params: T,
+    ) -> Pin<Box<dyn Future<Output = Result<R, Self::Error>> + marker::Send + 'async_trait>>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+        T: 'async_trait,
+        R: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+    {
+        let replay = self.0.clone();
+        let method = method.to_string();
+        let params = serde_json::to_value(&params);
+        async move {
+            let result = replay.request(&method, params?).await?;
+            Ok(serde_json::from_value(result)?)
+        }
+        .boxed()
+    }
+}
+
+impl PubsubClient for ReplayProvider {
+    type NotificationStream = BoxStream<'static, Box<RawValue>>;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        if id.into() != U256::from(HEADS_SUBSCRIPTION_ID) {
+            return Err(ReplayError::UnknownSubscription);
+        }
+        Ok(self.0.clone().heads())
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, _id: T) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// What would have been sent during the replay
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct ReplayReport {
+    /// Number of replayed heads
+    pub blocks: usize,
+    /// Number of requests answered from the recording
+    pub served: usize,
+    /// Number of requests missing in the recording by method
+    pub not_recorded: BTreeMap<String, usize>,
+    /// Number of requests forwarded to the archive node by method
+    pub from_archive: BTreeMap<String, usize>,
+    pub sent: Vec<SentTx>,
+    /// Sum of max fees of all sent transactions
+    pub max_fees: U256,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SentTx {
+    /// Number of the head on top of which transaction was sent
+    pub head: Option<U64>,
+    pub hash: TxHash,
+    pub nonce: U256,
+    pub to: Option<Address>,
+    pub gas: U256,
+    pub gas_price: U256,
+    pub value: U256,
+    pub outcome: SimulatedOutcome,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SimulatedOutcome {
+    /// Passed gas estimation against recorded pending state
+    Estimated { max_fee: U256 },
+    /// The very same transaction was sent during recording,
+    /// so its real receipt is known
+    Included {
+        status: Option<U64>,
+        gas_used: Option<U256>,
+        fee: U256,
+    },
+}
+
+#[derive(ThisError, Debug)]
+pub enum ReplayError {
+    #[error("{method} with params {params} is not recorded")]
+    NotRecorded { method: String, params: Value },
+
+    #[error("only newHeads subscription is replayed")]
+    UnknownSubscription,
+
+    #[error(transparent)]
+    JsonRpc(JsonRpcError),
+
+    #[error("archive node: {0}")]
+    Archive(HttpClientError),
+
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+
+    #[error("invalid raw transaction: {0}")]
+    Rlp(#[from] rlp::DecoderError),
+}
+
+impl RpcError for ReplayError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::JsonRpc(err) => Some(err),
+            Self::Archive(err) => err.as_error_response(),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Serde(err) => Some(err),
+            Self::Archive(err) => err.as_serde_error(),
+            _ => None,
+        }
+    }
+}
+
+impl From<ReplayError> for ProviderError {
+    fn from(e: ReplayError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(e))
+    }
+}
+
+fn block_param(method: &str) -> Option<usize> {
+    BLOCK_PARAMS
+        .iter()
+        .find(|(m, _)| *m == method)
+        .map(|(_, index)| *index)
+}
+
+/// Params of a state query without its block
+fn state_key(method: &str, params: &Value) -> Option<(String, String)> {
+    if !STATE_METHODS.contains(&method) {
+        return None;
+    }
+    let index = block_param(method)?;
+    let params = params.as_array()?;
+    Some((
+        method.to_string(),
+        Value::from(params[..index.min(params.len())].to_vec()).to_string(),
+    ))
+}
@@ -0,0 +1,232 @@
+use core::{fmt::Debug, future::Future, marker, pin::Pin, time::Duration};
+
+use ethers::{
+    core::rand::{thread_rng, Rng},
+    providers::{JsonRpcClient, JsonRpcError, ProviderError, PubsubClient, RpcError},
+    types::U256,
+};
+use futures::FutureExt;
+use impl_tools::autoimpl;
+use metrics::register_counter;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_with::{serde_as, DurationMilliSeconds};
+use thiserror::Error as ThisError;
+use tokio::time::{sleep, Instant};
+use tracing::{debug, warn};
+
+use sandwitch_engine::deadline::current_deadline;
+
+/// JSON-RPC error code of exceeded request limits
+const LIMIT_EXCEEDED: i64 = -32005;
+/// Sending is not retried, since the transaction could have reached
+/// the node before the connection failed
+const NOT_RETRIED: &[&str] = &["eth_sendRawTransaction", "eth_sendTransaction"];
+
+#[serde_as]
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct RetryConfig {
+    /// Retries of a single request, 0 disables retrying
+    #[serde(default = "RetryConfig::default_max_retries")]
+    pub max_retries: u32,
+
+    /// Backoff before the first retry, doubled for each next one
+    #[serde(rename = "initial_backoff_ms")]
+    #[serde(default = "RetryConfig::default_initial_backoff")]
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub initial_backoff: Duration,
+
+    #[serde(rename = "max_backoff_ms")]
+    #[serde(default = "RetryConfig::default_max_backoff")]
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub max_backoff: Duration,
+}
+
+impl RetryConfig {
+    fn default_max_retries() -> u32 {
+        3
+    }
+
+    fn default_initial_backoff() -> Duration {
+        Duration::from_millis(50)
+    }
+
+    fn default_max_backoff() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    /// Backoff before given retry with jitter, so that retries of
+    /// concurrent requests do not hit the node at once
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1u32 << retry.min(16))
+            .min(self.max_backoff);
+        backoff.mul_f64(thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: Self::default_max_retries(),
+            initial_backoff: Self::default_initial_backoff(),
+            max_backoff: Self::default_max_backoff(),
+        }
+    }
+}
+
+/// Retries requests failed with transient errors: dropped connections,
+/// timeouts and exceeded rate limits. Backoff never extends past the
+/// deadline of the pending block being processed, since the response
+/// would be of no use then.
+#[autoimpl(Deref using self.inner)]
+#[derive(Debug)]
+pub struct RetryProvider<P> {
+    inner: P,
+    cfg: RetryConfig,
+}
+
+impl<P> RetryProvider<P> {
+    pub fn new(client: P, cfg: RetryConfig) -> Self {
+        Self { inner: client, cfg }
+    }
+}
+
+/// Whether the request may succeed if repeated
+fn is_retryable<E>(err: &E) -> bool
+where
+    E: RpcError,
+{
+    if let Some(response) = err.as_error_response() {
+        let message = response.message.to_lowercase();
+        return response.code == LIMIT_EXCEEDED
+            || response.code == 429
+            || message.contains("limit exceeded")
+            || message.contains("rate limit");
+    }
+    // providers respond to exceeded limits with non-JSON bodies
+    let message = err.to_string().to_lowercase();
+    if err.as_serde_error().is_some() {
+        return message.contains("429") || message.contains("too many requests");
+    }
+    [
+        "429",
+        "too many requests",
+        "connection reset",
+        "connection closed",
+        "broken pipe",
+        "timeout",
+        "timed out",
+    ]
+    .into_iter()
+    .any(|transient| message.contains(transient))
+}
+
+impl<P> JsonRpcClient for RetryProvider<P>
+where
+    P: JsonRpcClient,
+    P::Error: 'static,
+{
+    type Error = RetryProviderError<P::Error>;
+
+    fn request<'life0, 'life1, 'async_trait, T, R>(
+        &'life0 self,
+        method: &'life1 str,
+        params: T,
+    ) -> Pin<Box<dyn Future<Output = Result<R, Self::Error>> + marker::Send + 'async_trait>>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+        T: 'async_trait,
+        R: 'async_trait,
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+    {
+        async move {
+            let params = serde_json::to_value(&params)?;
+            let mut retry = 0;
+            loop {
+                let err = match self.inner.request(method, &params).await {
+                    Ok(result) => return Ok(result),
+                    Err(err) => err,
+                };
+                if retry >= self.cfg.max_retries
+                    || NOT_RETRIED.contains(&method)
+                    || !is_retryable(&err)
+                {
+                    return Err(RetryProviderError::Inner(err));
+                }
+                let backoff = self.cfg.backoff(retry);
+                if current_deadline().is_some_and(|deadline| Instant::now() + backoff >= deadline) {
+                    debug!(method, %err, "no time left before block deadline to retry");
+                    return Err(RetryProviderError::Inner(err));
+                }
+                retry += 1;
+                warn!(method, retry, ?backoff, %err, "request failed, retrying...");
+                register_counter!("sandwitch_rpc_retries", "method" => method.to_string())
+                    .increment(1);
+                sleep(backoff).await;
+            }
+        }
+        .boxed()
+    }
+}
+
+impl<P> PubsubClient for RetryProvider<P>
+where
+    P: PubsubClient,
+    P::Error: 'static,
+{
+    type NotificationStream = P::NotificationStream;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        self.inner.subscribe(id).map_err(RetryProviderError::Inner)
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        self.inner
+            .unsubscribe(id)
+            .map_err(RetryProviderError::Inner)
+    }
+}
+
+#[derive(ThisError, Debug)]
+pub enum RetryProviderError<P> {
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Inner(P),
+}
+
+impl<P> RpcError for RetryProviderError<P>
+where
+    P: Into<ProviderError> + RpcError,
+{
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Inner(inner) => inner.as_error_response(),
+            Self::Serde(_) => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Inner(inner) => inner.as_serde_error(),
+            Self::Serde(err) => Some(err),
+        }
+    }
+}
+
+impl<P> From<RetryProviderError<P>> for ProviderError
+where
+    P: Into<ProviderError> + RpcError + 'static,
+{
+    fn from(e: RetryProviderError<P>) -> Self {
+        match e {
+            RetryProviderError::Inner(e) => e.into(),
+            RetryProviderError::Serde(e) => e.into(),
+        }
+    }
+}
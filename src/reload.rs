@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use ethers::providers::{JsonRpcClient, PubsubClient};
+use tracing::info;
+
+use sandwitch_engine::{
+    control::{EngineHandle, Timings},
+    monitor::Swappable,
+    MiddlewareStack,
+};
+
+use crate::{
+    app::{AppMonitor, AppProvider},
+    layers::{self, ConfigLayers},
+    App, AppConfig, Config,
+};
+
+/// Parts of the config which are used only on start along with the reason why
+const RESTART_REQUIRED: &[(&str, &str)] = &[
+    ("chain", "node is verified to be on the chain on start"),
+    ("network", "connection to the node is established on start"),
+    ("keystore", "wallet is decrypted on start"),
+    ("admin", "admin socket is bound on start"),
+    ("health", "health endpoint is bound on start"),
+    ("metrics", "metrics exporters are installed on start"),
+    ("engine.multicall", "engine and monitors are bound to the multicall"),
+    ("engine.simulation", "simulation backend is chosen on start"),
+    ("engine.tx_type", "transaction type is chosen on start"),
+    ("engine.base_token", "outcomes are accounted in the token chosen on start"),
+    ("engine.recorder", "recorder is initialized on start"),
+    ("engine.skip_failed_blocks", "engine run loop is configured on start"),
+    ("engine.pending_views_grace_ms", "engine run loop is configured on start"),
+    ("engine.circuit_breaker", "circuit breakers would lose their state"),
+];
+
+/// Applies changes of the config files to the running app
+pub struct Reloader<P>
+where
+    P: JsonRpcClient,
+    P::Error: 'static,
+{
+    layers: ConfigLayers,
+    current: toml::Value,
+    client: Arc<MiddlewareStack<AppProvider<P>>>,
+    monitor: Swappable<AppMonitor<P>>,
+    engine: EngineHandle<MiddlewareStack<AppProvider<P>>>,
+}
+
+impl<P> Reloader<P>
+where
+    P: PubsubClient + 'static,
+{
+    pub(crate) fn new(
+        layers: ConfigLayers,
+        current: toml::Value,
+        client: Arc<MiddlewareStack<AppProvider<P>>>,
+        monitor: Swappable<AppMonitor<P>>,
+        engine: EngineHandle<MiddlewareStack<AppProvider<P>>>,
+    ) -> Self {
+        Self {
+            layers,
+            current,
+            client,
+            monitor,
+            engine,
+        }
+    }
+
+    /// Re-reads the config files and stages their changes to be applied by the
+    /// engine between blocks. Nothing is changed if any of changes requires
+    /// restart or new config is invalid.
+    pub async fn reload(&mut self) -> anyhow::Result<()> {
+        let new = self.layers.load().await?;
+
+        let changed = |path: &str| lookup(&self.current, path) != lookup(&new, path);
+        let restart_required: Vec<_> = RESTART_REQUIRED
+            .iter()
+            .filter(|(path, _)| changed(path))
+            .map(|(path, reason)| format!("{path} ({reason})"))
+            .collect();
+        if !restart_required.is_empty() {
+            return Err(anyhow!(
+                "changes of {} require restart, keeping current config",
+                restart_required.join(", "),
+            ));
+        }
+        if new == self.current {
+            info!("config has not changed");
+            return Ok(());
+        }
+
+        let Config {
+            app: AppConfig {
+                engine, monitors, ..
+            },
+            ..
+        } = layers::parse(new.clone())?;
+        let monitor = if changed("monitors") {
+            info!("rebuilding monitors...");
+            Some(App::<P>::make_monitor(self.client.clone(), monitors).await?)
+        } else {
+            None
+        };
+
+        // only changed parts are applied, so that the ones changed at
+        // runtime through admin API are kept
+        let limits = changed("engine.limits").then_some(engine.limits);
+        let block_interval = changed("engine.block_interval_ms").then_some(engine.block_interval);
+        let tx_propagation_delay =
+            changed("engine.tx_propagation_delay_ms").then_some(engine.tx_propagation_delay);
+
+        let (handle, swappable) = (self.engine.clone(), self.monitor.clone());
+        self.engine.control().stage(Box::new(move || {
+            if let Some(limits) = limits {
+                handle.limits().set_config(limits);
+            }
+            if block_interval.is_some() || tx_propagation_delay.is_some() {
+                let Timings {
+                    block_interval: current_block_interval,
+                    tx_propagation_delay: current_tx_propagation_delay,
+                } = handle.control().timings();
+                handle.control().set_timings(Timings {
+                    block_interval: block_interval.unwrap_or(current_block_interval),
+                    tx_propagation_delay: tx_propagation_delay
+                        .unwrap_or(current_tx_propagation_delay),
+                });
+            }
+            if let Some(monitor) = monitor {
+                swappable.swap(monitor);
+            }
+        }));
+        self.current = new;
+        info!("config reloaded, changes will be applied before next block");
+        Ok(())
+    }
+}
+
+fn lookup<'a>(value: &'a toml::Value, path: &str) -> Option<&'a toml::Value> {
+    path.split('.').try_fold(value, |value, key| value.get(key))
+}